@@ -0,0 +1,62 @@
+@group(0) @binding(0)
+var tex: texture_2d<f32>;
+@group(0) @binding(1)
+var tex_sampler: sampler;
+
+struct Loupe {
+    // The top left corner on the surface
+    origin: vec2<f32>,
+    // The texel under the cursor, shown in the middle
+    center: vec2<i32>,
+    // The side of the zoomed in square
+    size: f32,
+    // Surface pixels per texel
+    zoom: f32,
+    // The height of the strip for the text below the square
+    info_height: f32,
+    border_color: vec4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> loupe: Loupe;
+
+const BORDER_WIDTH: f32 = 2.0;
+
+@vertex
+fn vs_main(@location(0) pos: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(pos, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let local = pos.xy - loupe.origin;
+    let extent = vec2<f32>(loupe.size, loupe.size + loupe.info_height);
+    if any(local < vec2<f32>(BORDER_WIDTH)) || any(local >= extent - BORDER_WIDTH) {
+        return loupe.border_color;
+    }
+    if local.y >= loupe.size {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.8);
+    }
+
+    // The texel under the cursor is centered in the square
+    let offset = local - loupe.size / 2.0 + loupe.zoom / 2.0;
+    let cell = floor(offset / loupe.zoom);
+    let within = offset - cell * loupe.zoom;
+    let texel = loupe.center + vec2<i32>(cell);
+
+    // Loading the texels directly is nearest filtering
+    var color = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    if all(texel >= vec2<i32>(0)) && all(texel < vec2<i32>(textureDimensions(tex))) {
+        color = textureLoad(tex, texel, 0);
+    }
+
+    // Outline the texel under the cursor
+    if all(cell == vec2<f32>(0.0)) && (any(within < vec2<f32>(1.0)) || any(within >= loupe.zoom - 1.0)) {
+        return loupe.border_color;
+    }
+    // The pixel grid
+    if any(within < vec2<f32>(1.0)) {
+        return vec4<f32>(color.rgb * 0.6, 1.0);
+    }
+    return vec4<f32>(color.rgb, 1.0);
+}
@@ -0,0 +1,75 @@
+@group(0) @binding(0)
+var tex: texture_2d<f32>;
+@group(0) @binding(1)
+var tex_sampler: sampler;
+
+struct Redaction {
+    // The top left and the exclusive bottom right corner of the texels to redact
+    bounds: vec4<i32>,
+    // Where the pixelated blocks start from
+    origin: vec2<i32>,
+    // Texels per pixel of the surface
+    scale: vec2<f32>,
+    // The blur radius or the block size in texels
+    size: i32,
+    // 0 to blur, 1 to pixelate
+    mode: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> redaction: Redaction;
+
+// Has to agree with the CPU version in annotation.rs
+const BLUR_TAPS: i32 = 6;
+const PIXELATE_SAMPLES: i32 = 8;
+
+@vertex
+fn vs_main(@location(0) pos: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(pos, 0.0, 1.0);
+}
+
+fn load(pos: vec2<i32>) -> vec4<f32> {
+    return textureLoad(tex, clamp(pos, redaction.bounds.xy, redaction.bounds.zw - 1), 0);
+}
+
+fn blur(pos: vec2<i32>) -> vec4<f32> {
+    var sum = vec4<f32>(0.0);
+    for (var y = -BLUR_TAPS; y <= BLUR_TAPS; y++) {
+        for (var x = -BLUR_TAPS; x <= BLUR_TAPS; x++) {
+            let offset = vec2<i32>(x, y) * redaction.size / BLUR_TAPS;
+            sum += load(pos + offset);
+        }
+    }
+    let taps = f32(BLUR_TAPS * 2 + 1);
+    return sum / (taps * taps);
+}
+
+fn pixelate(pos: vec2<i32>) -> vec4<f32> {
+    let block = redaction.origin + (pos - redaction.origin) / redaction.size * redaction.size;
+    let start = max(block, redaction.bounds.xy);
+    let end = min(block + redaction.size, redaction.bounds.zw);
+    let step = (redaction.size + PIXELATE_SAMPLES - 1) / PIXELATE_SAMPLES;
+
+    var sum = vec4<f32>(0.0);
+    var count = 0.0;
+    for (var y = start.y; y < end.y; y += step) {
+        for (var x = start.x; x < end.x; x += step) {
+            sum += load(vec2<i32>(x, y));
+            count += 1.0;
+        }
+    }
+    return sum / count;
+}
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = clamp(
+        vec2<i32>(floor(pos.xy * redaction.scale)),
+        redaction.bounds.xy,
+        redaction.bounds.zw - 1,
+    );
+    if redaction.mode == 0u {
+        return blur(texel);
+    }
+    return pixelate(texel);
+}
@@ -0,0 +1,1169 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use wgpu_text::glyph_brush::ab_glyph::{point, Font, FontArc, GlyphId, PxScale, ScaleFont};
+
+use crate::{
+    rendering::CIRCLE_EDGES,
+    types::{Color, Config, Rect},
+};
+
+/// Segments used to approximate an ellipse
+const ELLIPSE_SEGMENTS: u32 = 64;
+/// Segments used for the round joins and caps of strokes
+const JOIN_SEGMENTS: u32 = 16;
+/// Corner cutting passes to smooth the strokes with
+const SMOOTHING_PASSES: usize = 2;
+/// Taps on each side of a pixel averaged for the blur, `res/redact.wgsl` has to agree
+pub const BLUR_TAPS: i32 = 6;
+/// Most samples averaged along each side of a pixelated block, `res/redact.wgsl` has to agree
+pub const PIXELATE_SAMPLES: i32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Arrow,
+    Line,
+    Rectangle,
+    Ellipse,
+    Pen,
+    Highlighter,
+    Text,
+    Blur,
+    Pixelate,
+    Counter,
+}
+
+impl Tool {
+    /// The tool selected by typing the given text
+    pub fn from_key(text: &str) -> Option<Self> {
+        match text {
+            "a" => Some(Self::Arrow),
+            "l" => Some(Self::Line),
+            "r" => Some(Self::Rectangle),
+            "e" => Some(Self::Ellipse),
+            "p" => Some(Self::Pen),
+            "h" => Some(Self::Highlighter),
+            "t" => Some(Self::Text),
+            "b" => Some(Self::Blur),
+            "x" => Some(Self::Pixelate),
+            "n" => Some(Self::Counter),
+            _ => None,
+        }
+    }
+}
+
+/// What the annotations look like, taken from the config
+#[derive(Debug, Clone, Copy)]
+pub struct AnnotationStyle {
+    pub color: Color,
+    pub width: f32,
+    pub highlighter_color: Color,
+    pub highlighter_width: f32,
+    pub text_color: Color,
+    pub text_size: f32,
+    pub blur_radius: f32,
+    pub pixelate_size: f32,
+    pub counter_color: Color,
+    pub counter_radius: f32,
+}
+
+impl AnnotationStyle {
+    /// Start a new annotation with the tool at the given position
+    pub fn annotation(&self, tool: Tool, position: (i32, i32)) -> Annotation {
+        let (color, width) = match tool {
+            Tool::Highlighter => (self.highlighter_color, self.highlighter_width),
+            Tool::Text => (self.text_color, self.text_size),
+            Tool::Blur => (self.color, self.blur_radius),
+            Tool::Pixelate => (self.color, self.pixelate_size),
+            Tool::Counter => (self.counter_color, self.counter_radius),
+            _ => (self.color, self.width),
+        };
+
+        Annotation {
+            shape: Shape::new(tool, position),
+            color,
+            width,
+        }
+    }
+}
+
+impl From<&Config> for AnnotationStyle {
+    fn from(config: &Config) -> Self {
+        Self {
+            color: config.annotation_color,
+            width: config.annotation_width as f32,
+            highlighter_color: config.highlighter_color,
+            highlighter_width: config.highlighter_width as f32,
+            text_color: config.annotation_text_color,
+            text_size: config.annotation_text_size as f32,
+            blur_radius: config.blur_radius as f32,
+            pixelate_size: config.pixelate_size as f32,
+            counter_color: config.counter_color,
+            counter_radius: config.counter_radius as f32,
+        }
+    }
+}
+
+/// Shapes are in global logical coordinates, the simple ones span from where the drag started to where it ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Arrow {
+        start: (i32, i32),
+        end: (i32, i32),
+    },
+    Line {
+        start: (i32, i32),
+        end: (i32, i32),
+    },
+    Rectangle {
+        start: (i32, i32),
+        end: (i32, i32),
+    },
+    Ellipse {
+        start: (i32, i32),
+        end: (i32, i32),
+    },
+    /// Drawn freehand with the pen or the highlighter
+    Stroke {
+        points: Vec<(i32, i32)>,
+    },
+    /// A single line of text starting from the top left corner at `position`
+    Text {
+        position: (i32, i32),
+        text: String,
+    },
+    /// A filled circle showing its number among the counters, see [`counter_number`]
+    Counter {
+        center: (i32, i32),
+    },
+    /// Hides what is within the rectangle, see [`Redaction`]
+    Redaction {
+        kind: Redaction,
+        start: (i32, i32),
+        end: (i32, i32),
+    },
+}
+
+/// How a redaction hides what is under it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redaction {
+    Blur,
+    Pixelate,
+}
+
+impl Shape {
+    pub fn new(tool: Tool, position: (i32, i32)) -> Self {
+        match tool {
+            Tool::Arrow => Self::Arrow {
+                start: position,
+                end: position,
+            },
+            Tool::Line => Self::Line {
+                start: position,
+                end: position,
+            },
+            Tool::Rectangle => Self::Rectangle {
+                start: position,
+                end: position,
+            },
+            Tool::Ellipse => Self::Ellipse {
+                start: position,
+                end: position,
+            },
+            Tool::Pen | Tool::Highlighter => Self::Stroke {
+                points: vec![position],
+            },
+            Tool::Text => Self::Text {
+                position,
+                text: String::new(),
+            },
+            Tool::Counter => Self::Counter { center: position },
+            Tool::Blur | Tool::Pixelate => Self::Redaction {
+                kind: if tool == Tool::Blur {
+                    Redaction::Blur
+                } else {
+                    Redaction::Pixelate
+                },
+                start: position,
+                end: position,
+            },
+        }
+    }
+
+    pub fn drag_to(&mut self, position: (i32, i32)) {
+        match self {
+            Self::Arrow { end, .. }
+            | Self::Line { end, .. }
+            | Self::Rectangle { end, .. }
+            | Self::Ellipse { end, .. }
+            | Self::Redaction { end, .. } => *end = position,
+            Self::Stroke { points } => {
+                if points.last() != Some(&position) {
+                    points.push(position);
+                }
+            }
+            // Dragging places the counter
+            Self::Counter { center } => *center = position,
+            Self::Text { .. } => (),
+        }
+    }
+
+    /// Whether the shape has nothing to draw, like after a click without dragging
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Arrow { start, end } | Self::Line { start, end } => start == end,
+            Self::Rectangle { start, end }
+            | Self::Ellipse { start, end }
+            | Self::Redaction { start, end, .. } => start.0 == end.0 || start.1 == end.1,
+            // A click leaves a dot
+            Self::Stroke { points } => points.is_empty(),
+            Self::Text { text, .. } => text.is_empty(),
+            Self::Counter { .. } => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub shape: Shape,
+    pub color: Color,
+    /// The line width, the font size of text, the radius of counters, or the blur
+    /// radius or the block size of redactions
+    pub width: f32,
+}
+
+impl Annotation {
+    /// Triangulate the annotation in global logical coordinates.
+    ///
+    /// Both the overlay and the exported image are drawn from the same
+    /// triangles, so what is seen is what ends up in the screenshot.
+    pub fn to_mesh(&self) -> Mesh {
+        let mut mesh = Mesh::default();
+        let width = self.width;
+
+        match &self.shape {
+            &Shape::Line { start, end } => {
+                mesh.push_segment(to_f32(start), to_f32(end), width, width / 2.0);
+            }
+            &Shape::Arrow { start, end } => {
+                let (start, end) = (to_f32(start), to_f32(end));
+                let length = distance(start, end);
+                let dir = direction(start, end);
+                let head_length = (width * 4.0).max(12.0).min(length);
+                let base = [end[0] - dir[0] * head_length, end[1] - dir[1] * head_length];
+                let half = head_length / 2.0;
+
+                mesh.push_segment(start, base, width, 0.0);
+                mesh.push_triangle([
+                    end,
+                    [base[0] - dir[1] * half, base[1] + dir[0] * half],
+                    [base[0] + dir[1] * half, base[1] - dir[0] * half],
+                ]);
+            }
+            &Shape::Rectangle { start, end } => {
+                let (x0, y0) = (start.0.min(end.0) as f32, start.1.min(end.1) as f32);
+                let (x1, y1) = (start.0.max(end.0) as f32, start.1.max(end.1) as f32);
+                let extend = width / 2.0;
+
+                mesh.push_segment([x0, y0], [x1, y0], width, extend);
+                mesh.push_segment([x1, y0], [x1, y1], width, extend);
+                mesh.push_segment([x1, y1], [x0, y1], width, extend);
+                mesh.push_segment([x0, y1], [x0, y0], width, extend);
+            }
+            &Shape::Ellipse { start, end } => {
+                let center = [
+                    (start.0 + end.0) as f32 / 2.0,
+                    (start.1 + end.1) as f32 / 2.0,
+                ];
+                let radius = [
+                    (start.0 - end.0).abs() as f32 / 2.0,
+                    (start.1 - end.1).abs() as f32 / 2.0,
+                ];
+                let point = |i: u32, offset: f32| {
+                    let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * std::f32::consts::TAU;
+                    [
+                        center[0] + (radius[0] + offset).max(0.0) * angle.cos(),
+                        center[1] + (radius[1] + offset).max(0.0) * angle.sin(),
+                    ]
+                };
+
+                for i in 0..ELLIPSE_SEGMENTS {
+                    mesh.push_quad([
+                        point(i, width / 2.0),
+                        point(i + 1, width / 2.0),
+                        point(i + 1, -width / 2.0),
+                        point(i, -width / 2.0),
+                    ]);
+                }
+            }
+            Shape::Stroke { points } => {
+                let points = smoothed(points);
+
+                for pair in points.windows(2) {
+                    mesh.push_segment(pair[0], pair[1], width, 0.0);
+                }
+                // Round joins and caps so the segments connect without gaps
+                for point in points {
+                    mesh.push_disc(point, width / 2.0, JOIN_SEGMENTS);
+                }
+            }
+            // Drawn with the font instead
+            Shape::Text { .. } => (),
+            // The number is drawn with the font on top
+            &Shape::Counter { center } => mesh.push_disc(to_f32(center), width, CIRCLE_EDGES),
+            // The area to redact
+            &Shape::Redaction { start, end, .. } => {
+                let (x0, y0) = (start.0.min(end.0) as f32, start.1.min(end.1) as f32);
+                let (x1, y1) = (start.0.max(end.0) as f32, start.1.max(end.1) as f32);
+
+                mesh.push_quad([[x0, y0], [x1, y0], [x1, y1], [x0, y1]]);
+            }
+        }
+
+        mesh
+    }
+
+    /// Map a redaction to the pixels of an image showing `image_rect` of the global
+    /// space, with `scale` pixels per logical pixel. Only what is within `clip` is redacted.
+    pub fn to_pixel_redaction(
+        &self,
+        image_rect: &Rect<i32>,
+        clip: &Rect<i32>,
+        scale: [f32; 2],
+    ) -> Option<PixelRedaction> {
+        let Shape::Redaction { kind, start, end } = self.shape else {
+            return None;
+        };
+
+        let to_pixel = |x: i32, y: i32| {
+            [
+                ((x - image_rect.x) as f32 * scale[0]).round() as i32,
+                ((y - image_rect.y) as f32 * scale[1]).round() as i32,
+            ]
+        };
+        let origin = to_pixel(start.0.min(end.0), start.1.min(end.1));
+        let end = to_pixel(start.0.max(end.0), start.1.max(end.1));
+        let clip_min = to_pixel(clip.x, clip.y);
+        let clip_max = to_pixel(clip.x + clip.width, clip.y + clip.height);
+
+        let min = [origin[0].max(clip_min[0]), origin[1].max(clip_min[1])];
+        let max = [end[0].min(clip_max[0]), end[1].min(clip_max[1])];
+        if min[0] >= max[0] || min[1] >= max[1] {
+            return None;
+        }
+
+        Some(PixelRedaction {
+            kind,
+            min,
+            max,
+            origin,
+            size: ((self.width * scale[0]).round() as i32).max(1),
+        })
+    }
+}
+
+/// A redaction in the pixels of an image, the same for the overlay and the exported image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelRedaction {
+    pub kind: Redaction,
+    /// The top left corner of the pixels to redact
+    pub min: [i32; 2],
+    /// The bottom right corner, exclusive
+    pub max: [i32; 2],
+    /// Where the pixelated blocks start from
+    pub origin: [i32; 2],
+    /// The blur radius or the block size in pixels
+    pub size: i32,
+}
+
+/// Triangles to fill with the color of an annotation
+#[derive(Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    fn push_triangle(&mut self, corners: [[f32; 2]; 3]) {
+        let first = self.vertices.len() as u32;
+        self.vertices.extend(corners);
+        self.indices.extend([first, first + 1, first + 2]);
+    }
+
+    fn push_quad(&mut self, corners: [[f32; 2]; 4]) {
+        let first = self.vertices.len() as u32;
+        self.vertices.extend(corners);
+        self.indices
+            .extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+
+    fn push_disc(&mut self, center: [f32; 2], radius: f32, segments: u32) {
+        let first = self.vertices.len() as u32;
+        self.vertices.push(center);
+        for i in 0..segments {
+            let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+            self.vertices.push([
+                center[0] + radius * angle.cos(),
+                center[1] + radius * angle.sin(),
+            ]);
+            self.indices
+                .extend([first, first + 1 + i, first + 1 + (i + 1) % segments]);
+        }
+    }
+
+    /// A straight line of the given width, extended past both ends by `extend`
+    fn push_segment(&mut self, start: [f32; 2], end: [f32; 2], width: f32, extend: f32) {
+        let dir = direction(start, end);
+        let normal = [-dir[1] * width / 2.0, dir[0] * width / 2.0];
+        let start = [start[0] - dir[0] * extend, start[1] - dir[1] * extend];
+        let end = [end[0] + dir[0] * extend, end[1] + dir[1] * extend];
+
+        self.push_quad([
+            [start[0] + normal[0], start[1] + normal[1]],
+            [end[0] + normal[0], end[1] + normal[1]],
+            [end[0] - normal[0], end[1] - normal[1]],
+            [start[0] - normal[0], start[1] - normal[1]],
+        ]);
+    }
+
+    /// The pixels of an image of the given size the vertices are on, clamped to it
+    fn bounds(&self, width: u32, height: u32) -> Rect<u32> {
+        let (min, max) =
+            self.vertices
+                .iter()
+                .fold(([f32::MAX; 2], [f32::MIN; 2]), |(min, max), vertex| {
+                    (
+                        [min[0].min(vertex[0]), min[1].min(vertex[1])],
+                        [max[0].max(vertex[0]), max[1].max(vertex[1])],
+                    )
+                });
+
+        let min_x = (min[0].floor().max(0.0) as u32).min(width);
+        let min_y = (min[1].floor().max(0.0) as u32).min(height);
+        let max_x = (max[0].ceil().max(0.0) as u32).clamp(min_x, width);
+        let max_y = (max[1].ceil().max(0.0) as u32).clamp(min_y, height);
+
+        Rect::new(min_x, min_y, max_x - min_x, max_y - min_y)
+    }
+
+    /// Which of the 4x4 samples of each pixel within the bounds the triangles cover
+    fn coverage(&self, bounds: &Rect<u32>) -> Vec<u16> {
+        let mut coverage = vec![0_u16; (bounds.width * bounds.height) as usize];
+        let (right, bottom) = (bounds.x + bounds.width, bounds.y + bounds.height);
+
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| self.vertices[triangle[i] as usize]);
+
+            let min_x = (a[0].min(b[0]).min(c[0]).floor().max(0.0) as u32).max(bounds.x);
+            let min_y = (a[1].min(b[1]).min(c[1]).floor().max(0.0) as u32).max(bounds.y);
+            let max_x = (a[0].max(b[0]).max(c[0]).ceil().max(0.0) as u32).min(right);
+            let max_y = (a[1].max(b[1]).max(c[1]).ceil().max(0.0) as u32).min(bottom);
+
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    // Overlapping triangles of the same annotation must not blend twice,
+                    // so the covered samples are combined instead of the coverage
+                    let pixel =
+                        &mut coverage[((y - bounds.y) * bounds.width + x - bounds.x) as usize];
+                    for sample in 0..16 {
+                        let point = [
+                            x as f32 + (sample % 4) as f32 / 4.0 + 0.125,
+                            y as f32 + (sample / 4) as f32 / 4.0 + 0.125,
+                        ];
+                        if in_triangle(point, a, b, c) {
+                            *pixel |= 1 << sample;
+                        }
+                    }
+                }
+            }
+        }
+
+        coverage
+    }
+}
+
+/// The number of the counter at `index`, counting the counters placed before it
+pub fn counter_number(annotations: &[Annotation], index: usize) -> usize {
+    annotations[..index]
+        .iter()
+        .filter(|annotation| matches!(annotation.shape, Shape::Counter { .. }))
+        .count()
+        + 1
+}
+
+/// The color to write on a counter with, whichever of black and white stands out more
+pub fn counter_text_color(color: &Color) -> Color {
+    let luminance = 0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b;
+    let value = if luminance > 0.4 { 0.0 } else { 1.0 };
+
+    Color {
+        r: value,
+        g: value,
+        b: value,
+        a: color.a,
+    }
+}
+
+/// The font size of the number on a counter of the given radius
+pub fn counter_text_size(radius: f32) -> f32 {
+    radius * 1.2
+}
+
+/// Draw the annotations into the image of the given region of the global space
+pub fn rasterize(
+    image: &mut DynamicImage,
+    annotations: &[Annotation],
+    rect: &Rect<i32>,
+    font: &FontArc,
+) {
+    if annotations.is_empty() || rect.width <= 0 || rect.height <= 0 {
+        return;
+    }
+
+    let mut rgba = image.to_rgba8();
+    draw_shapes(&mut rgba, annotations, rect);
+    // Like in the overlay the text is written over all the shapes
+    draw_text_annotations(&mut rgba, annotations, rect, font);
+
+    *image = DynamicImage::ImageRgba8(rgba);
+}
+
+/// How many pixels of the image there are to a logical pixel of the region,
+/// as the image can be of a higher resolution
+fn pixel_scale(image: &RgbaImage, rect: &Rect<i32>) -> [f32; 2] {
+    [
+        image.width() as f32 / rect.width as f32,
+        image.height() as f32 / rect.height as f32,
+    ]
+}
+
+/// Draw everything of the annotations but the text
+fn draw_shapes(rgba: &mut RgbaImage, annotations: &[Annotation], rect: &Rect<i32>) {
+    let (width, height) = rgba.dimensions();
+    let scale = pixel_scale(rgba, rect);
+
+    // Like in the overlay the redactions apply to the screenshot, under all the other annotations
+    for annotation in annotations {
+        if let Some(redaction) = annotation.to_pixel_redaction(rect, rect, scale) {
+            redact(rgba, &redaction);
+        }
+    }
+
+    for annotation in annotations {
+        if let Shape::Redaction { .. } | Shape::Text { .. } = annotation.shape {
+            continue;
+        }
+
+        let mut mesh = annotation.to_mesh();
+        for vertex in &mut mesh.vertices {
+            *vertex = [
+                (vertex[0] - rect.x as f32) * scale[0],
+                (vertex[1] - rect.y as f32) * scale[1],
+            ];
+        }
+
+        let bounds = mesh.bounds(width, height);
+        for (i, samples) in mesh.coverage(&bounds).into_iter().enumerate() {
+            if samples != 0 {
+                let x = bounds.x + i as u32 % bounds.width;
+                let y = bounds.y + i as u32 / bounds.width;
+                blend(
+                    rgba.get_pixel_mut(x, y),
+                    &annotation.color,
+                    samples.count_ones() as f32 / 16.0,
+                );
+            }
+        }
+    }
+}
+
+/// Write the text annotations and the numbers on the counters
+fn draw_text_annotations(
+    rgba: &mut RgbaImage,
+    annotations: &[Annotation],
+    rect: &Rect<i32>,
+    font: &FontArc,
+) {
+    let scale = pixel_scale(rgba, rect);
+
+    for (i, annotation) in annotations.iter().enumerate() {
+        match &annotation.shape {
+            Shape::Text { position, text } => {
+                let position = [
+                    (position.0 - rect.x) as f32 * scale[0],
+                    (position.1 - rect.y) as f32 * scale[1],
+                ];
+                draw_text(
+                    rgba,
+                    font,
+                    position,
+                    text,
+                    annotation.width * scale[1],
+                    &annotation.color,
+                );
+            }
+            Shape::Counter { center } => {
+                let size = counter_text_size(annotation.width) * scale[1];
+                let text = counter_number(annotations, i).to_string();
+                let font_scaled = font.as_scaled(PxScale::from(size));
+                // Centered like glyph_brush centers the overlay text
+                let position = [
+                    (center.0 - rect.x) as f32 * scale[0] - text_width(font, &text, size) / 2.0,
+                    (center.1 - rect.y) as f32 * scale[1]
+                        - (font_scaled.ascent() - font_scaled.descent()) / 2.0,
+                ];
+                draw_text(
+                    rgba,
+                    font,
+                    position,
+                    &text,
+                    size,
+                    &counter_text_color(&annotation.color),
+                );
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Blur or pixelate the pixels like `res/redact.wgsl` does, replacing what was there
+fn redact(image: &mut RgbaImage, redaction: &PixelRedaction) {
+    let min = [redaction.min[0].max(0), redaction.min[1].max(0)];
+    let max = [
+        redaction.max[0].min(image.width() as i32),
+        redaction.max[1].min(image.height() as i32),
+    ];
+    if min[0] >= max[0] || min[1] >= max[1] {
+        return;
+    }
+
+    let (width, height) = ((max[0] - min[0]) as usize, (max[1] - min[1]) as usize);
+    // Averaged in linear space, which is what the shader gets from the sRGB texture
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in min[1]..max[1] {
+        for x in min[0]..max[0] {
+            let [r, g, b, a] = image.get_pixel(x as u32, y as u32).0;
+            pixels.push([
+                srgb_to_linear(r as f32 / 255.0),
+                srgb_to_linear(g as f32 / 255.0),
+                srgb_to_linear(b as f32 / 255.0),
+                a as f32 / 255.0,
+            ]);
+        }
+    }
+
+    let pixels = match redaction.kind {
+        Redaction::Blur => {
+            // The shader averages a grid of taps, which is the same as averaging the rows then the columns
+            let offsets = (-BLUR_TAPS..=BLUR_TAPS)
+                .map(|tap| tap * redaction.size / BLUR_TAPS)
+                .collect::<Vec<_>>();
+            let tap = |position: usize, offset: i32, length: usize| {
+                (position as i32 + offset).clamp(0, length as i32 - 1) as usize
+            };
+            let average = |samples: &mut dyn Iterator<Item = [f32; 4]>| {
+                let mut sum = [0.0; 4];
+                for sample in samples {
+                    for channel in 0..4 {
+                        sum[channel] += sample[channel];
+                    }
+                }
+                sum.map(|channel| channel / offsets.len() as f32)
+            };
+
+            let mut rows = vec![[0.0; 4]; pixels.len()];
+            for (i, pixel) in rows.iter_mut().enumerate() {
+                let (x, y) = (i % width, i / width);
+                *pixel = average(
+                    &mut offsets
+                        .iter()
+                        .map(|offset| pixels[y * width + tap(x, *offset, width)]),
+                );
+            }
+            let mut columns = vec![[0.0; 4]; pixels.len()];
+            for (i, pixel) in columns.iter_mut().enumerate() {
+                let (x, y) = (i % width, i / width);
+                *pixel = average(
+                    &mut offsets
+                        .iter()
+                        .map(|offset| rows[tap(y, *offset, height) * width + x]),
+                );
+            }
+            columns
+        }
+        Redaction::Pixelate => {
+            let step = (redaction.size + PIXELATE_SAMPLES - 1) / PIXELATE_SAMPLES;
+            let mut pixelated = vec![[0.0; 4]; pixels.len()];
+            let blocks = |min: i32, max: i32, origin: i32| {
+                let first = (min - origin).div_euclid(redaction.size);
+                (first..)
+                    .map(move |block| origin + block * redaction.size)
+                    .take_while(move |start| *start < max)
+                    .map(move |start| {
+                        (
+                            start.max(min) - min,
+                            (start + redaction.size).min(max) - min,
+                        )
+                    })
+            };
+
+            for (y0, y1) in blocks(min[1], max[1], redaction.origin[1]) {
+                for (x0, x1) in blocks(min[0], max[0], redaction.origin[0]) {
+                    let mut sum = [0.0; 4];
+                    let mut count = 0.0;
+                    for y in (y0..y1).step_by(step as usize) {
+                        for x in (x0..x1).step_by(step as usize) {
+                            let sample = pixels[y as usize * width + x as usize];
+                            for channel in 0..4 {
+                                sum[channel] += sample[channel];
+                            }
+                            count += 1.0;
+                        }
+                    }
+                    let average = sum.map(|channel| channel / count);
+
+                    for y in y0..y1 {
+                        for x in x0..x1 {
+                            pixelated[y as usize * width + x as usize] = average;
+                        }
+                    }
+                }
+            }
+            pixelated
+        }
+    };
+
+    for (i, [r, g, b, a]) in pixels.into_iter().enumerate() {
+        let x = min[0] as u32 + (i % width) as u32;
+        let y = min[1] as u32 + (i / width) as u32;
+        image.put_pixel(
+            x,
+            y,
+            Rgba([
+                (linear_to_srgb(r) * 255.0).round() as u8,
+                (linear_to_srgb(g) * 255.0).round() as u8,
+                (linear_to_srgb(b) * 255.0).round() as u8,
+                (a * 255.0).round() as u8,
+            ]),
+        );
+    }
+}
+
+/// Lay out a line of text the way glyph_brush does for the overlay, and blend it into the image
+fn draw_text(
+    image: &mut RgbaImage,
+    font: &FontArc,
+    position: [f32; 2],
+    text: &str,
+    size: f32,
+    color: &Color,
+) {
+    let font = font.as_scaled(PxScale::from(size));
+    let mut caret = point(position[0], position[1] + font.ascent());
+    let mut previous: Option<GlyphId> = None;
+
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            caret.x += font.kern(previous, id);
+        }
+        previous = Some(id);
+
+        let glyph = id.with_scale_and_position(font.scale(), caret);
+        caret.x += font.h_advance(id);
+
+        let Some(outline) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|x, y, coverage| {
+            let x = bounds.min.x as i32 + x as i32;
+            let y = bounds.min.y as i32 + y as i32;
+
+            if x >= 0 && y >= 0 && (x as u32) < image.width() && (y as u32) < image.height() {
+                blend(image.get_pixel_mut(x as u32, y as u32), color, coverage);
+            }
+        });
+    }
+}
+
+/// How far the caret advances over the text
+fn text_width(font: &FontArc, text: &str, size: f32) -> f32 {
+    let font = font.as_scaled(PxScale::from(size));
+    let mut width = 0.0;
+    let mut previous: Option<GlyphId> = None;
+
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        previous = Some(id);
+        width += font.h_advance(id);
+    }
+
+    width
+}
+
+/// Blend like the overlay does, which happens in linear space as it is drawn on an sRGB surface
+fn blend(pixel: &mut Rgba<u8>, color: &Color, coverage: f32) {
+    let alpha = color.a * coverage;
+
+    for (channel, color) in pixel.0.iter_mut().zip([color.r, color.g, color.b]) {
+        let linear = srgb_to_linear(*channel as f32 / 255.0) * (1.0 - alpha) + color * alpha;
+        *channel = (linear_to_srgb(linear) * 255.0).round() as u8;
+    }
+    pixel.0[3] = (pixel.0[3] as f32 * (1.0 - alpha) + 255.0 * alpha).round() as u8;
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Smooth out the corners of a polyline, keeping its ends in place
+fn smoothed(points: &[(i32, i32)]) -> Vec<[f32; 2]> {
+    let mut points = points.iter().copied().map(to_f32).collect::<Vec<_>>();
+
+    for _ in 0..SMOOTHING_PASSES {
+        if points.len() < 3 {
+            break;
+        }
+
+        let mut smoothed = vec![points[0]];
+        for pair in points.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            smoothed.push([a[0] * 0.75 + b[0] * 0.25, a[1] * 0.75 + b[1] * 0.25]);
+            smoothed.push([a[0] * 0.25 + b[0] * 0.75, a[1] * 0.25 + b[1] * 0.75]);
+        }
+        smoothed.push(points[points.len() - 1]);
+
+        points = smoothed;
+    }
+
+    points
+}
+
+fn to_f32(point: (i32, i32)) -> [f32; 2] {
+    [point.0 as f32, point.1 as f32]
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt()
+}
+
+/// Unit vector pointing from `a` to `b`
+fn direction(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    let length = distance(a, b);
+    if length == 0.0 {
+        [1.0, 0.0]
+    } else {
+        [(b[0] - a[0]) / length, (b[1] - a[1]) / length]
+    }
+}
+
+fn in_triangle(p: [f32; 2], a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> bool {
+    let edge =
+        |a: [f32; 2], b: [f32; 2]| (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0]);
+    let (ab, bc, ca) = (edge(a, b), edge(b, c), edge(c, a));
+
+    (ab >= 0.0 && bc >= 0.0 && ca >= 0.0) || (ab <= 0.0 && bc <= 0.0 && ca <= 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbaImage;
+
+    use super::*;
+
+    const RED: Color = Color {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+
+    fn annotation(shape: Shape) -> Annotation {
+        Annotation {
+            shape,
+            color: RED,
+            width: 2.0,
+        }
+    }
+
+    /// The same font the overlay would use by default, only for the tests that write text
+    fn font() -> FontArc {
+        let fc = fontconfig::Fontconfig::new().unwrap();
+        let font = fc.find("monospace", None).unwrap();
+        FontArc::try_from_vec(std::fs::read(font.path).unwrap()).unwrap()
+    }
+
+    /// Like `rasterize`, without the text that needs a font
+    fn rasterize_shapes(image: &mut DynamicImage, annotations: &[Annotation], rect: &Rect<i32>) {
+        let mut rgba = image.to_rgba8();
+        draw_shapes(&mut rgba, annotations, rect);
+        *image = DynamicImage::ImageRgba8(rgba);
+    }
+
+    fn white_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([255; 4])))
+    }
+
+    fn pixel(image: &DynamicImage, x: u32, y: u32) -> [u8; 4] {
+        image.to_rgba8().get_pixel(x, y).0
+    }
+
+    #[test]
+    fn test_rectangle_outline() {
+        let mut image = white_image(100, 100);
+        let rectangle = annotation(Shape::Rectangle {
+            start: (70, 80),
+            end: (20, 10),
+        });
+        rasterize_shapes(&mut image, &[rectangle], &Rect::new(0, 0, 100, 100));
+
+        assert_eq!(pixel(&image, 20, 50), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 45, 79), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 45, 45), [255; 4]);
+        assert_eq!(pixel(&image, 10, 10), [255; 4]);
+    }
+
+    #[test]
+    fn test_drawn_at_full_resolution() {
+        // A 2x scaled output, offset in the global space
+        let mut image = white_image(200, 200);
+        let line = annotation(Shape::Line {
+            start: (1010, 525),
+            end: (1090, 525),
+        });
+        rasterize_shapes(&mut image, &[line], &Rect::new(1000, 500, 100, 100));
+
+        // Two logical pixels wide are four physical ones
+        for y in 48..52 {
+            assert_eq!(pixel(&image, 100, y), [255, 0, 0, 255]);
+        }
+        assert_eq!(pixel(&image, 100, 46), [255; 4]);
+        assert_eq!(pixel(&image, 100, 53), [255; 4]);
+    }
+
+    #[test]
+    fn test_partly_outside_the_image() {
+        let mut image = white_image(40, 40);
+        let line = annotation(Shape::Line {
+            start: (-20, 20),
+            end: (30, 20),
+        });
+        let mesh = line.to_mesh();
+        assert_eq!(mesh.bounds(40, 40), Rect::new(0, 19, 31, 2));
+
+        rasterize_shapes(&mut image, &[line], &Rect::new(0, 0, 40, 40));
+        assert_eq!(pixel(&image, 0, 20), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 35, 20), [255; 4]);
+    }
+
+    #[test]
+    fn test_translucent_overlaps_blend_once() {
+        let mut image = white_image(50, 50);
+        let mut rectangle = annotation(Shape::Rectangle {
+            start: (10, 10),
+            end: (40, 40),
+        });
+        rectangle.color.a = 0.5;
+        rasterize_shapes(&mut image, &[rectangle], &Rect::new(0, 0, 50, 50));
+
+        // The corners are covered by two of the sides
+        assert_eq!(pixel(&image, 10, 10), pixel(&image, 25, 10));
+    }
+
+    #[test]
+    fn test_smoothed_stroke_keeps_ends() {
+        let points = smoothed(&[(0, 0), (10, 0), (10, 10)]);
+
+        assert_eq!(points.first(), Some(&[0.0, 0.0]));
+        assert_eq!(points.last(), Some(&[10.0, 10.0]));
+        // The corner is cut
+        assert!(!points.contains(&[10.0, 0.0]));
+    }
+
+    #[test]
+    fn test_highlighter_is_translucent() {
+        let mut image = white_image(40, 40);
+        let mut stroke = annotation(Shape::Stroke {
+            points: vec![(5, 20), (20, 20), (20, 25), (35, 20)],
+        });
+        stroke.color.a = 0.5;
+        stroke.width = 8.0;
+        rasterize_shapes(&mut image, &[stroke], &Rect::new(0, 0, 40, 40));
+
+        // Evenly translucent, even where the segments and joins overlap
+        let [r, g, b, a] = pixel(&image, 20, 20);
+        assert_eq!((r, a), (255, 255));
+        assert!(g > 100 && g < 255 && g == b);
+        assert_eq!(pixel(&image, 10, 20), [r, g, b, a]);
+        assert_eq!(pixel(&image, 20, 2), [255; 4]);
+    }
+
+    #[test]
+    fn test_text_scaled_with_the_image() {
+        let text = Annotation {
+            shape: Shape::Text {
+                position: (10, 10),
+                text: "WW".to_string(),
+            },
+            color: RED,
+            width: 20.0,
+        };
+
+        let bounds = |scale: u32| {
+            let mut image = white_image(100 * scale, 50 * scale);
+            rasterize(
+                &mut image,
+                std::slice::from_ref(&text),
+                &Rect::new(0, 0, 100, 50),
+                &font(),
+            );
+
+            let image = image.to_rgba8();
+            let drawn = image
+                .enumerate_pixels()
+                .filter(|(_, _, pixel)| pixel.0 != [255; 4])
+                .map(|(x, y, _)| (x, y))
+                .collect::<Vec<_>>();
+            let min_x = drawn.iter().map(|(x, _)| *x).min().unwrap();
+            let max_x = drawn.iter().map(|(x, _)| *x).max().unwrap();
+            (min_x, max_x)
+        };
+
+        let (min_x, max_x) = bounds(1);
+        assert!(min_x >= 10 && max_x < 40);
+
+        // Twice the pixels on a scaled output
+        let (scaled_min_x, scaled_max_x) = bounds(2);
+        assert!(scaled_min_x.abs_diff(min_x * 2) <= 2);
+        assert!(scaled_max_x.abs_diff(max_x * 2) <= 2);
+    }
+
+    /// Alternating black and white columns
+    fn striped_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, _| {
+            Rgba([if x % 2 == 0 { 0 } else { 255 }, 0, 0, 255])
+        }))
+    }
+
+    fn redaction(kind: Redaction, start: (i32, i32), end: (i32, i32), size: f32) -> Annotation {
+        Annotation {
+            shape: Shape::Redaction { kind, start, end },
+            color: RED,
+            width: size,
+        }
+    }
+
+    #[test]
+    fn test_pixelate_averages_blocks() {
+        let mut image = striped_image(40, 40);
+        let pixelate = redaction(Redaction::Pixelate, (30, 30), (10, 10), 4.0);
+        rasterize_shapes(&mut image, &[pixelate], &Rect::new(0, 0, 40, 40));
+
+        // The stripes average out within the blocks, in linear space
+        let [r, ..] = pixel(&image, 10, 10);
+        assert!(r > 170 && r < 200);
+        for (x, y) in [(13, 13), (17, 10), (29, 29)] {
+            assert_eq!(pixel(&image, x, y), pixel(&image, 10, 10));
+        }
+        // Outside is left alone
+        assert_eq!(pixel(&image, 8, 20), [0, 0, 0, 255]);
+        assert_eq!(pixel(&image, 31, 20), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_blur_stays_within_the_redaction() {
+        let mut image = striped_image(60, 20);
+        let blur = redaction(Redaction::Blur, (10, 0), (50, 20), 6.0);
+        let line = annotation(Shape::Line {
+            start: (20, 10),
+            end: (40, 10),
+        });
+        rasterize_shapes(&mut image, &[line, blur], &Rect::new(0, 0, 60, 20));
+
+        // The stripes are gone
+        for x in 16..44 {
+            let [r, ..] = pixel(&image, x, 2);
+            assert!(r > 170 && r < 200);
+        }
+        // Only what is within the redaction is blurred, even next to its edges
+        for x in [10, 49] {
+            let [r, ..] = pixel(&image, x, 2);
+            assert!(r > 0 && r < 255);
+        }
+        assert_eq!(pixel(&image, 9, 2), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 50, 2), [0, 0, 0, 255]);
+        // Annotations are drawn over the redactions
+        assert_eq!(pixel(&image, 30, 10), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_counter() {
+        let counter = |x| Annotation {
+            shape: Shape::Counter { center: (x, 20) },
+            color: RED,
+            width: 15.0,
+        };
+        let annotations = [
+            counter(20),
+            annotation(Shape::Stroke { points: vec![] }),
+            counter(60),
+        ];
+        assert_eq!(counter_number(&annotations, 0), 1);
+        assert_eq!(counter_number(&annotations, 2), 2);
+
+        let mut image = white_image(80, 40);
+        rasterize(&mut image, &annotations, &Rect::new(0, 0, 80, 40), &font());
+
+        // Filled, with the number in a color that stands out from it
+        assert_eq!(pixel(&image, 8, 20), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 3, 20), [255; 4]);
+        // Centered on the circles
+        let image = image.to_rgba8();
+        for center in [20, 60] {
+            let written = image
+                .enumerate_pixels()
+                .filter(|(x, y, pixel)| {
+                    x.abs_diff(center).pow(2) + y.abs_diff(20).pow(2) < 12 * 12 && pixel.0[1] > 128
+                })
+                .map(|(x, y, _)| (x as f32, y as f32))
+                .collect::<Vec<_>>();
+            let count = written.len() as f32;
+            let x = written.iter().map(|(x, _)| x).sum::<f32>() / count;
+            let y = written.iter().map(|(_, y)| y).sum::<f32>() / count;
+
+            assert!(count > 10.0);
+            assert!((x - center as f32).abs() < 3.0 && (y - 20.0).abs() < 3.0);
+        }
+    }
+
+    #[test]
+    fn test_arrow_head() {
+        let arrow = annotation(Shape::Arrow {
+            start: (0, 0),
+            end: (100, 0),
+        })
+        .to_mesh();
+
+        // The shaft and the head
+        assert_eq!(arrow.indices.len(), 9);
+        assert!(arrow.vertices.contains(&[100.0, 0.0]));
+        assert!(arrow
+            .vertices
+            .iter()
+            .any(|vertex| vertex[0] == 88.0 && vertex[1].abs() == 6.0));
+    }
+}
@@ -0,0 +1,532 @@
+use image::DynamicImage;
+use smithay_client_toolkit::{
+    error::GlobalError,
+    globals::ProvidesBoundGlobal,
+    output::OutputInfo,
+    reexports::client::{
+        delegate_noop, event_created_child,
+        protocol::{wl_buffer, wl_output, wl_shm},
+        Connection, Dispatch, EventQueue, QueueHandle, WEnum,
+    },
+    shm::raw::RawPool,
+};
+
+use crate::{
+    protocols::{
+        ext_foreign_toplevel_list::{
+            ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+            ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1},
+        },
+        ext_image_capture_source::{
+            ext_foreign_toplevel_image_capture_source_manager_v1::ExtForeignToplevelImageCaptureSourceManagerV1,
+            ext_image_capture_source_v1::ExtImageCaptureSourceV1,
+            ext_output_image_capture_source_manager_v1::ExtOutputImageCaptureSourceManagerV1,
+        },
+        ext_image_copy_capture::{
+            ext_image_copy_capture_frame_v1::{self, ExtImageCopyCaptureFrameV1},
+            ext_image_copy_capture_manager_v1::{self, ExtImageCopyCaptureManagerV1},
+            ext_image_copy_capture_session_v1::{self, ExtImageCopyCaptureSessionV1},
+        },
+    },
+    window::WindowDescriptor,
+};
+
+use super::{
+    logical_rect,
+    shm::{apply_transform, is_supported_format, to_image, BufferInfo},
+    CaptureBackend, CaptureContext, CaptureError, CaptureNotAvailable, CapturedOutput,
+    InitializeCaptureBackend,
+};
+
+#[derive(Debug, PartialEq)]
+enum CaptureStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+struct Capture {
+    session: ExtImageCopyCaptureSessionV1,
+    buffer_size: Option<(u32, u32)>,
+    /// The first shm format offered by the compositor that we know how to read
+    format: Option<wl_shm::Format>,
+    /// Whether the buffer constraints are complete and the frame can be captured
+    constraints_done: bool,
+    transform: wl_output::Transform,
+    status: CaptureStatus,
+    frame: Option<ExtImageCopyCaptureFrameV1>,
+    pool: Option<RawPool>,
+    buffer: Option<wl_buffer::WlBuffer>,
+}
+
+struct Toplevel {
+    handle: ExtForeignToplevelHandleV1,
+    title: String,
+    app_id: String,
+}
+
+/// State of the dedicated event queue used while capturing
+#[derive(Default)]
+struct ExtImageCopyState {
+    captures: Vec<Capture>,
+    toplevels: Vec<Toplevel>,
+}
+
+/// Captures with the `ext-image-copy-capture-v1` protocol, which can also
+/// capture individual windows when the compositor supports toplevel sources
+pub struct ExtImageCopyBackend {
+    event_queue: EventQueue<ExtImageCopyState>,
+    state: ExtImageCopyState,
+    copy_manager: ExtImageCopyCaptureManagerV1,
+    output_source_manager: ExtOutputImageCaptureSourceManagerV1,
+    toplevel_source_manager: Option<ExtForeignToplevelImageCaptureSourceManagerV1>,
+    toplevel_list: Option<ExtForeignToplevelListV1>,
+    shm: wl_shm::WlShm,
+}
+
+impl ExtImageCopyBackend {
+    /// Capture every source, returning the images in the same order
+    fn capture_sources(
+        &mut self,
+        sources: Vec<ExtImageCaptureSourceV1>,
+    ) -> Result<Vec<DynamicImage>, CaptureError> {
+        let qh = self.event_queue.handle();
+
+        self.state.captures = sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| Capture {
+                session: self.copy_manager.create_session(
+                    source,
+                    ext_image_copy_capture_manager_v1::Options::empty(),
+                    &qh,
+                    i,
+                ),
+                buffer_size: None,
+                format: None,
+                constraints_done: false,
+                transform: wl_output::Transform::Normal,
+                status: CaptureStatus::Pending,
+                frame: None,
+                pool: None,
+                buffer: None,
+            })
+            .collect();
+
+        let result = self.wait_for_captures(&qh);
+
+        for capture in self.state.captures.drain(..) {
+            if let Some(frame) = capture.frame {
+                frame.destroy();
+            }
+            if let Some(buffer) = capture.buffer {
+                buffer.destroy();
+            }
+            capture.session.destroy();
+        }
+        for source in sources {
+            source.destroy();
+        }
+
+        result
+    }
+
+    fn wait_for_captures(
+        &mut self,
+        qh: &QueueHandle<ExtImageCopyState>,
+    ) -> Result<Vec<DynamicImage>, CaptureError> {
+        while self
+            .state
+            .captures
+            .iter()
+            .any(|capture| capture.status == CaptureStatus::Pending)
+        {
+            self.event_queue
+                .blocking_dispatch(&mut self.state)
+                .map_err(|why| CaptureError::Failed(why.to_string()))?;
+
+            for i in 0..self.state.captures.len() {
+                let capture = &self.state.captures[i];
+                if !capture.constraints_done
+                    || capture.frame.is_some()
+                    || capture.status != CaptureStatus::Pending
+                {
+                    continue;
+                }
+
+                let (Some((width, height)), Some(format)) = (capture.buffer_size, capture.format)
+                else {
+                    return Err(CaptureError::Failed(
+                        "No supported shm buffer format was offered".to_string(),
+                    ));
+                };
+
+                let info = buffer_info((width, height), format);
+                let mut pool = RawPool::new((info.stride * info.height) as usize, &*self)
+                    .map_err(|why| CaptureError::Failed(why.to_string()))?;
+                let buffer = pool.create_buffer(
+                    0,
+                    width as i32,
+                    height as i32,
+                    info.stride as i32,
+                    format,
+                    (),
+                    qh,
+                );
+
+                let capture = &mut self.state.captures[i];
+                let frame = capture.session.create_frame(qh, i);
+                frame.attach_buffer(&buffer);
+                frame.damage_buffer(0, 0, width as i32, height as i32);
+                frame.capture();
+
+                capture.frame = Some(frame);
+                capture.pool = Some(pool);
+                capture.buffer = Some(buffer);
+            }
+        }
+
+        self.state
+            .captures
+            .iter_mut()
+            .map(|capture| {
+                if capture.status == CaptureStatus::Failed {
+                    return Err(CaptureError::Failed(
+                        "Compositor failed to copy the capture source".to_string(),
+                    ));
+                }
+
+                // All of these are always set once the frame is ready
+                let info = buffer_info(capture.buffer_size.unwrap(), capture.format.unwrap());
+                let pool = capture.pool.as_mut().unwrap();
+
+                Ok(apply_transform(
+                    to_image(pool.mmap(), &info, false)?,
+                    capture.transform,
+                ))
+            })
+            .collect()
+    }
+}
+
+impl CaptureBackend for ExtImageCopyBackend {
+    fn capture_outputs(
+        &mut self,
+        outputs: &[(wl_output::WlOutput, OutputInfo)],
+    ) -> Result<Vec<CapturedOutput>, CaptureError> {
+        let rects = outputs
+            .iter()
+            .map(|(_, info)| {
+                logical_rect(info).ok_or(CaptureError::Failed(
+                    "Can't determine the geometry of an output".to_string(),
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let qh = self.event_queue.handle();
+        let sources = outputs
+            .iter()
+            .map(|(output, _)| self.output_source_manager.create_source(output, &qh, ()))
+            .collect();
+
+        Ok(self
+            .capture_sources(sources)?
+            .into_iter()
+            .zip(rects)
+            .map(|(image, rect)| CapturedOutput { rect, image })
+            .collect())
+    }
+
+    fn capture_window(&mut self, window: &WindowDescriptor) -> Result<DynamicImage, CaptureError> {
+        let (Some(source_manager), Some(_)) = (&self.toplevel_source_manager, &self.toplevel_list)
+        else {
+            return Err(CaptureError::Unsupported);
+        };
+
+        // Receive the current toplevels and their properties
+        self.event_queue
+            .roundtrip(&mut self.state)
+            .map_err(|why| CaptureError::Failed(why.to_string()))?;
+
+        let index = find_toplevel(
+            self.state
+                .toplevels
+                .iter()
+                .map(|toplevel| (toplevel.app_id.as_str(), toplevel.title.as_str())),
+            window,
+        )
+        .ok_or(CaptureError::Failed(format!(
+            "No toplevel matches window \"{}\"",
+            window.title
+        )))?;
+        let toplevel = &self.state.toplevels[index];
+
+        let qh = self.event_queue.handle();
+        let source = source_manager.create_source(&toplevel.handle, &qh, ());
+
+        Ok(self.capture_sources(vec![source])?.remove(0))
+    }
+
+    fn captures_windows(&self) -> bool {
+        self.toplevel_source_manager.is_some() && self.toplevel_list.is_some()
+    }
+}
+
+impl InitializeCaptureBackend for ExtImageCopyBackend {
+    fn try_new(context: &CaptureContext) -> Result<Box<dyn CaptureBackend>, CaptureNotAvailable> {
+        let event_queue = context.conn.new_event_queue::<ExtImageCopyState>();
+        let qh = event_queue.handle();
+
+        let copy_manager = context
+            .globals
+            .bind(&qh, 1..=1, ())
+            .map_err(|_| CaptureNotAvailable::Unsupported)?;
+        let output_source_manager = context
+            .globals
+            .bind(&qh, 1..=1, ())
+            .map_err(|_| CaptureNotAvailable::Unsupported)?;
+        let shm = context
+            .globals
+            .bind(&qh, 1..=1, ())
+            .map_err(|_| CaptureNotAvailable::Unsupported)?;
+
+        // Window capture is optional, and needs both of these
+        let (toplevel_source_manager, toplevel_list) = match (
+            context.globals.bind(&qh, 1..=1, ()),
+            context.globals.bind(&qh, 1..=1, ()),
+        ) {
+            (Ok(source_manager), Ok(list)) => (Some(source_manager), Some(list)),
+            _ => (None, None),
+        };
+
+        Ok(Box::new(ExtImageCopyBackend {
+            event_queue,
+            state: ExtImageCopyState::default(),
+            copy_manager,
+            output_source_manager,
+            toplevel_source_manager,
+            toplevel_list,
+            shm,
+        }))
+    }
+}
+
+impl ProvidesBoundGlobal<wl_shm::WlShm, 1> for ExtImageCopyBackend {
+    fn bound_global(&self) -> Result<wl_shm::WlShm, GlobalError> {
+        Ok(self.shm.clone())
+    }
+}
+
+impl Drop for ExtImageCopyBackend {
+    fn drop(&mut self) {
+        for toplevel in self.state.toplevels.drain(..) {
+            toplevel.handle.destroy();
+        }
+        if let Some(list) = &self.toplevel_list {
+            list.stop();
+        }
+        if let Some(source_manager) = &self.toplevel_source_manager {
+            source_manager.destroy();
+        }
+        self.output_source_manager.destroy();
+        self.copy_manager.destroy();
+    }
+}
+
+delegate_noop!(ExtImageCopyState: ignore wl_shm::WlShm);
+delegate_noop!(ExtImageCopyState: ignore wl_buffer::WlBuffer);
+delegate_noop!(ExtImageCopyState: ExtImageCopyCaptureManagerV1);
+delegate_noop!(ExtImageCopyState: ExtOutputImageCaptureSourceManagerV1);
+delegate_noop!(ExtImageCopyState: ExtForeignToplevelImageCaptureSourceManagerV1);
+delegate_noop!(ExtImageCopyState: ExtImageCaptureSourceV1);
+
+impl Dispatch<ExtImageCopyCaptureSessionV1, usize> for ExtImageCopyState {
+    fn event(
+        state: &mut Self,
+        _: &ExtImageCopyCaptureSessionV1,
+        event: ext_image_copy_capture_session_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use ext_image_copy_capture_session_v1::Event;
+
+        let capture = &mut state.captures[*index];
+
+        match event {
+            Event::BufferSize { width, height } => capture.buffer_size = Some((width, height)),
+            Event::ShmFormat {
+                format: WEnum::Value(format),
+            } if capture.format.is_none() && is_supported_format(format) => {
+                capture.format = Some(format)
+            }
+            Event::Done => capture.constraints_done = true,
+            Event::Stopped => capture.status = CaptureStatus::Failed,
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureFrameV1, usize> for ExtImageCopyState {
+    fn event(
+        state: &mut Self,
+        _: &ExtImageCopyCaptureFrameV1,
+        event: ext_image_copy_capture_frame_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use ext_image_copy_capture_frame_v1::Event;
+
+        let capture = &mut state.captures[*index];
+
+        match event {
+            Event::Transform {
+                transform: WEnum::Value(transform),
+            } => capture.transform = transform,
+            Event::Ready => capture.status = CaptureStatus::Ready,
+            Event::Failed { reason } => {
+                log::warn!("Frame capture failed: {:?}", reason);
+                capture.status = CaptureStatus::Failed;
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<ExtForeignToplevelListV1, ()> for ExtImageCopyState {
+    fn event(
+        state: &mut Self,
+        _: &ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.push(Toplevel {
+                handle: toplevel,
+                title: String::new(),
+                app_id: String::new(),
+            });
+        }
+    }
+
+    event_created_child!(ExtImageCopyState, ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ExtForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ExtForeignToplevelHandleV1, ()> for ExtImageCopyState {
+    fn event(
+        state: &mut Self,
+        handle: &ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use ext_foreign_toplevel_handle_v1::Event;
+
+        let Some(index) = state
+            .toplevels
+            .iter()
+            .position(|toplevel| toplevel.handle == *handle)
+        else {
+            return;
+        };
+
+        match event {
+            Event::Title { title } => state.toplevels[index].title = title,
+            Event::AppId { app_id } => state.toplevels[index].app_id = app_id,
+            Event::Closed => {
+                state.toplevels.remove(index).handle.destroy();
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The layout of the buffers we allocate, tightly packed 32-bit pixels
+fn buffer_info((width, height): (u32, u32), format: wl_shm::Format) -> BufferInfo {
+    BufferInfo {
+        format,
+        width,
+        height,
+        stride: width * 4,
+    }
+}
+
+/// The index of the toplevel, given as `(app_id, title)`, that shows the window. Falls back to
+/// the app ID alone when the title changed, as long as no other toplevel has the same app ID.
+fn find_toplevel<'a>(
+    toplevels: impl Iterator<Item = (&'a str, &'a str)> + Clone,
+    window: &WindowDescriptor,
+) -> Option<usize> {
+    toplevels
+        .clone()
+        .position(|(app_id, title)| app_id == window.class && title == window.title)
+        .or_else(|| {
+            let mut candidates = toplevels
+                .enumerate()
+                .filter(|(_, (app_id, _))| *app_id == window.class);
+
+            // Only trust the class if it is unambiguous
+            match (candidates.next(), candidates.next()) {
+                (Some((index, _)), None) => Some(index),
+                _ => None,
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rect;
+
+    fn window(class: &str, title: &str) -> WindowDescriptor {
+        WindowDescriptor {
+            initial_title: String::new(),
+            title: title.to_string(),
+            initial_class: String::new(),
+            class: class.to_string(),
+            rect: Some(Rect::new(0, 0, 100, 100)),
+        }
+    }
+
+    #[test]
+    fn test_buffer_info() {
+        let info = buffer_info((3, 2), wl_shm::Format::Argb8888);
+        assert_eq!(info.stride, 12);
+
+        // Stored as BGRA, every row exactly the stride long
+        let mut data = vec![0; (info.stride * info.height) as usize];
+        data[..4].copy_from_slice(&[10, 20, 30, 255]);
+        data[20..24].copy_from_slice(&[40, 50, 60, 128]);
+
+        let image = to_image(&data, &info, false).unwrap().to_rgba8();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(image.get_pixel(0, 0).0, [30, 20, 10, 255]);
+        assert_eq!(image.get_pixel(2, 1).0, [60, 50, 40, 128]);
+
+        // Never picked from the offered formats, since it can't be read
+        assert!(!is_supported_format(wl_shm::Format::Rgb565));
+        assert!(to_image(&data, &buffer_info((3, 2), wl_shm::Format::Rgb565), false).is_err());
+    }
+
+    #[test]
+    fn test_find_toplevel() {
+        let toplevels = [
+            ("Alacritty", "~"),
+            ("firefox", "Mozilla Firefox"),
+            ("Alacritty", "vim"),
+        ];
+        let find = |window| find_toplevel(toplevels.iter().copied(), &window);
+
+        assert_eq!(find(window("Alacritty", "vim")), Some(2));
+        // The title changed since the window list was made
+        assert_eq!(find(window("firefox", "GitHub - Mozilla Firefox")), Some(1));
+        assert_eq!(find(window("Alacritty", "htop")), None);
+        assert_eq!(find(window("foot", "~")), None);
+    }
+}
@@ -0,0 +1,177 @@
+use smithay_client_toolkit::{output::OutputInfo, reexports::client::protocol::wl_output};
+
+use crate::types::Rect;
+
+use super::{
+    logical_rect, split, CaptureBackend, CaptureContext, CaptureError, CaptureNotAvailable,
+    CapturedOutput, InitializeCaptureBackend,
+};
+
+/// Loads the "captured" images from disk, useful for testing without real screen contents.
+///
+/// A single image is treated as a screenshot of the whole desktop and split
+/// between the outputs. Otherwise there must be one image per output, which
+/// are assigned to the outputs from left to right, top to bottom.
+pub struct FileBackend {
+    paths: Vec<String>,
+}
+
+impl FileBackend {
+    pub fn new(paths: Vec<String>) -> Self {
+        Self { paths }
+    }
+
+    /// Produce the captured outputs for the given output geometries
+    pub fn load(&self, rects: &[Rect<i32>]) -> Result<Vec<CapturedOutput>, CaptureError> {
+        let images = self
+            .paths
+            .iter()
+            .map(|path| {
+                image::open(path)
+                    .map_err(|why| CaptureError::InvalidImage(format!("{}: {}", path, why)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match images.len() {
+            1 => Ok(split(&images[0], rects)),
+            len if len == rects.len() => {
+                let mut order = (0..rects.len()).collect::<Vec<_>>();
+                order.sort_by_key(|i| (rects[*i].x, rects[*i].y));
+
+                let mut captured = order
+                    .into_iter()
+                    .zip(images)
+                    .map(|(i, image)| {
+                        (
+                            i,
+                            CapturedOutput {
+                                rect: rects[i],
+                                image,
+                            },
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                // Back into the order the outputs were given in
+                captured.sort_by_key(|(i, _)| *i);
+
+                Ok(captured.into_iter().map(|(_, output)| output).collect())
+            }
+            len => Err(CaptureError::Failed(format!(
+                "Got {} capture files for {} outputs",
+                len,
+                rects.len()
+            ))),
+        }
+    }
+}
+
+impl CaptureBackend for FileBackend {
+    fn capture_outputs(
+        &mut self,
+        outputs: &[(wl_output::WlOutput, OutputInfo)],
+    ) -> Result<Vec<CapturedOutput>, CaptureError> {
+        let rects = outputs
+            .iter()
+            .map(|(_, info)| {
+                logical_rect(info).ok_or(CaptureError::Failed(
+                    "Can't determine the geometry of an output".to_string(),
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.load(&rects)
+    }
+}
+
+impl InitializeCaptureBackend for FileBackend {
+    fn try_new(context: &CaptureContext) -> Result<Box<dyn CaptureBackend>, CaptureNotAvailable> {
+        if context.options.capture_files.is_empty() {
+            Err(CaptureNotAvailable::NotRequested)
+        } else {
+            Ok(Box::new(FileBackend::new(
+                context.options.capture_files.clone(),
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    /// An image saved in the temp dir, removed again at the end of the test
+    struct TempImage {
+        path: std::path::PathBuf,
+    }
+
+    impl TempImage {
+        /// The name has to be unique among the tests running in parallel
+        fn save(name: &str, image: &RgbaImage) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("watershot-{}-{}.png", std::process::id(), name));
+            image.save(&path).unwrap();
+            Self { path }
+        }
+
+        fn filled(name: &str, width: u32, height: u32, color: [u8; 4]) -> Self {
+            Self::save(name, &RgbaImage::from_pixel(width, height, Rgba(color)))
+        }
+
+        fn path(&self) -> String {
+            self.path.to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for TempImage {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    #[test]
+    fn test_single_file_is_split_between_outputs() {
+        let mut image = RgbaImage::from_pixel(400, 100, Rgba([255, 0, 0, 255]));
+        for x in 200..400 {
+            for y in 0..100 {
+                image.put_pixel(x, y, Rgba([0, 255, 0, 255]));
+            }
+        }
+        let desktop = TempImage::save("desktop", &image);
+
+        // The outputs use negative coordinates and are listed right to left
+        let rects = [Rect::new(0, 0, 100, 50), Rect::new(-100, 0, 100, 50)];
+        let captured = FileBackend::new(vec![desktop.path()]).load(&rects).unwrap();
+
+        assert_eq!(captured[0].rect, rects[0]);
+        assert_eq!(captured[0].image.width(), 200);
+        assert_eq!(
+            captured[0].image.to_rgba8().get_pixel(0, 0).0,
+            [0, 255, 0, 255]
+        );
+        assert_eq!(
+            captured[1].image.to_rgba8().get_pixel(199, 99).0,
+            [255, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn test_files_are_assigned_by_position() {
+        let left = TempImage::filled("left", 10, 10, [1, 1, 1, 255]);
+        let right = TempImage::filled("right", 20, 20, [2, 2, 2, 255]);
+
+        let rects = [Rect::new(10, 0, 10, 10), Rect::new(0, 0, 10, 10)];
+        let captured = FileBackend::new(vec![left.path(), right.path()])
+            .load(&rects)
+            .unwrap();
+
+        assert_eq!(captured[0].image.width(), 20);
+        assert_eq!(captured[1].image.width(), 10);
+
+        let extra = TempImage::filled("extra", 1, 1, [0, 0, 0, 255]);
+        let backend = FileBackend::new(vec![extra.path(); 3]);
+        assert!(backend.load(&rects).is_err());
+    }
+}
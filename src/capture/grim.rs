@@ -0,0 +1,72 @@
+use std::{io::Cursor, process::Command};
+
+use image::DynamicImage;
+use smithay_client_toolkit::{output::OutputInfo, reexports::client::protocol::wl_output};
+
+use super::{
+    logical_rect, CaptureBackend, CaptureContext, CaptureError, CaptureNotAvailable,
+    CapturedOutput, InitializeCaptureBackend,
+};
+
+/// Captures by running `grim` once per output, only used when requested with `--grim`
+pub struct GrimBackend {
+    grim: String,
+}
+
+impl CaptureBackend for GrimBackend {
+    fn capture_outputs(
+        &mut self,
+        outputs: &[(wl_output::WlOutput, OutputInfo)],
+    ) -> Result<Vec<CapturedOutput>, CaptureError> {
+        outputs
+            .iter()
+            .map(|(_, info)| {
+                let name = info.name.as_deref().ok_or(CaptureError::Failed(
+                    "grim needs output names to capture individual outputs".to_string(),
+                ))?;
+                let rect = logical_rect(info).ok_or(CaptureError::Failed(format!(
+                    "Can't determine the geometry of output {}",
+                    name
+                )))?;
+
+                Ok(CapturedOutput {
+                    rect,
+                    image: capture_output(&self.grim, name)?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl InitializeCaptureBackend for GrimBackend {
+    fn try_new(context: &CaptureContext) -> Result<Box<dyn CaptureBackend>, CaptureNotAvailable> {
+        match &context.options.grim {
+            Some(grim) => Ok(Box::new(GrimBackend { grim: grim.clone() })),
+            None => Err(CaptureNotAvailable::NotRequested),
+        }
+    }
+}
+
+/// Capture a single output by running `grim` and parsing its PPM output
+fn capture_output(grim: &str, output_name: &str) -> Result<DynamicImage, CaptureError> {
+    let output = Command::new(grim)
+        .arg("-t")
+        .arg("ppm")
+        .arg("-o")
+        .arg(output_name)
+        .arg("-")
+        .output()
+        .map_err(|why| CaptureError::Failed(format!("Failed to run grim command: {}", why)))?;
+
+    if !output.status.success() {
+        return Err(CaptureError::Failed(format!(
+            "grim exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    image::io::Reader::with_format(Cursor::new(output.stdout), image::ImageFormat::Pnm)
+        .decode()
+        .map_err(|why| CaptureError::InvalidImage(why.to_string()))
+}
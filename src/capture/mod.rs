@@ -0,0 +1,206 @@
+use std::fmt;
+
+use image::{imageops, DynamicImage, RgbaImage};
+use smithay_client_toolkit::{
+    output::OutputInfo,
+    reexports::client::{globals::GlobalList, protocol::wl_output, Connection},
+};
+
+use crate::{
+    traits::ToLocal,
+    types::{Options, Rect},
+    window::WindowDescriptor,
+};
+
+pub mod ext_image_copy;
+pub mod file;
+pub mod grim;
+pub mod portal;
+pub mod screencopy;
+mod shm;
+
+/// A single output as captured by a [`CaptureBackend`]
+pub struct CapturedOutput {
+    /// Logical geometry of the output in the global compositor space
+    pub rect: Rect<i32>,
+    pub image: DynamicImage,
+}
+
+pub trait CaptureBackend {
+    /// Capture the given outputs, returning them in the same order
+    fn capture_outputs(
+        &mut self,
+        outputs: &[(wl_output::WlOutput, OutputInfo)],
+    ) -> Result<Vec<CapturedOutput>, CaptureError>;
+
+    /// Capture the contents of a single window, including parts that are
+    /// covered by other windows or outside of any output
+    fn capture_window(&mut self, _window: &WindowDescriptor) -> Result<DynamicImage, CaptureError> {
+        Err(CaptureError::Unsupported)
+    }
+
+    /// Whether [`CaptureBackend::capture_window`] is supported
+    fn captures_windows(&self) -> bool {
+        false
+    }
+}
+
+/// Everything a capture backend may need to set itself up
+pub struct CaptureContext<'a> {
+    pub conn: &'a Connection,
+    pub globals: &'a GlobalList,
+    pub options: &'a Options,
+}
+
+pub trait InitializeCaptureBackend {
+    fn try_new(context: &CaptureContext) -> Result<Box<dyn CaptureBackend>, CaptureNotAvailable>;
+}
+
+pub enum CaptureNotAvailable {
+    /// The backend is only used when explicitly asked for
+    NotRequested,
+    /// The compositor lacks the protocols the backend needs
+    Unsupported,
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    /// The compositor refused or failed to copy the output contents
+    Failed(String),
+    /// The captured data could not be decoded into an image
+    InvalidImage(String),
+    /// The backend has no way to perform this kind of capture
+    Unsupported,
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed(why) => write!(f, "Capture failed: {}", why),
+            Self::InvalidImage(why) => write!(f, "Invalid image data: {}", why),
+            Self::Unsupported => write!(f, "Capture not supported by this backend"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+/// Pick the first available backend, explicitly requested ones take precedence
+pub fn get_preferred_backend(context: &CaptureContext) -> Option<Box<dyn CaptureBackend>> {
+    file::FileBackend::try_new(context)
+        .or_else(|_| grim::GrimBackend::try_new(context))
+        .or_else(|_| ext_image_copy::ExtImageCopyBackend::try_new(context))
+        .or_else(|_| screencopy::ScreencopyBackend::try_new(context))
+        .or_else(|_| portal::PortalBackend::try_new(context))
+        .ok()
+}
+
+/// The logical geometry of an output, if the compositor has provided it
+pub fn logical_rect(info: &OutputInfo) -> Option<Rect<i32>> {
+    let (x, y) = info.logical_position?;
+    let (width, height) = info.logical_size?;
+
+    Some(Rect::new(x, y, width, height))
+}
+
+/// Combine the per-output images into a single image spanning `area`.
+///
+/// Like grim, the result is rendered at the highest scale of all outputs so
+/// no output loses detail.
+pub fn composite(area: &Rect<i32>, outputs: &[CapturedOutput]) -> DynamicImage {
+    let scale = outputs
+        .iter()
+        .map(|output| output.image.width() as f32 / output.rect.width as f32)
+        .fold(1.0, f32::max);
+
+    let mut canvas = RgbaImage::new(
+        (area.width as f32 * scale) as u32,
+        (area.height as f32 * scale) as u32,
+    );
+
+    for CapturedOutput { rect, image } in outputs {
+        let width = (rect.width as f32 * scale) as u32;
+        let height = (rect.height as f32 * scale) as u32;
+
+        let image = if image.width() == width && image.height() == height {
+            image.to_rgba8()
+        } else {
+            imageops::resize(image, width, height, imageops::FilterType::Triangle)
+        };
+
+        imageops::overlay(
+            &mut canvas,
+            &image,
+            ((rect.x - area.x) as f32 * scale) as i64,
+            ((rect.y - area.y) as f32 * scale) as i64,
+        );
+    }
+
+    DynamicImage::ImageRgba8(canvas)
+}
+
+/// Crop a rectangle in logical coordinates relative to the image origin out of
+/// an image that has `scale` physical pixels per logical pixel
+pub fn crop(image: &DynamicImage, rect: &Rect<i32>, scale: f32) -> DynamicImage {
+    image.crop_imm(
+        (rect.x as f32 * scale) as u32,
+        (rect.y as f32 * scale) as u32,
+        (rect.width as f32 * scale) as u32,
+        (rect.height as f32 * scale) as u32,
+    )
+}
+
+/// Split an image spanning all of `rects` into one image per rect
+pub fn split(image: &DynamicImage, rects: &[Rect<i32>]) -> Vec<CapturedOutput> {
+    let mut area = Rect::default();
+    for rect in rects {
+        area.extend(rect);
+    }
+
+    let scale = image.width() as f32 / area.width as f32;
+
+    rects
+        .iter()
+        .map(|rect| CapturedOutput {
+            rect: *rect,
+            image: crop(image, &rect.to_local(&area), scale),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn test_composite_and_crop_mixed_scales() {
+        // A 2x output on the left and a 1x output on the right
+        let left = RgbaImage::from_pixel(200, 200, Rgba([255, 0, 0, 255]));
+        let right = RgbaImage::from_pixel(100, 100, Rgba([0, 0, 255, 255]));
+        let outputs = [
+            CapturedOutput {
+                rect: Rect::new(0, 0, 100, 100),
+                image: DynamicImage::ImageRgba8(left),
+            },
+            CapturedOutput {
+                rect: Rect::new(100, 0, 100, 100),
+                image: DynamicImage::ImageRgba8(right),
+            },
+        ];
+
+        let mut area = Rect::default();
+        for output in &outputs {
+            area.extend(&output.rect);
+        }
+
+        let image = composite(&area, &outputs);
+        assert_eq!((image.width(), image.height()), (400, 200));
+
+        let cropped = crop(&image, &Rect::new(90, 10, 20, 20), 2.0).to_rgba8();
+        assert_eq!((cropped.width(), cropped.height()), (40, 40));
+        assert_eq!(cropped.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(cropped.get_pixel(39, 39).0, [0, 0, 255, 255]);
+    }
+}
@@ -0,0 +1,355 @@
+use std::{collections::HashMap, ffi::OsString, os::unix::ffi::OsStringExt, path::PathBuf};
+
+use image::DynamicImage;
+use smithay_client_toolkit::{output::OutputInfo, reexports::client::protocol::wl_output};
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+};
+
+use super::{
+    logical_rect, split, CaptureBackend, CaptureContext, CaptureError, CaptureNotAvailable,
+    CapturedOutput, InitializeCaptureBackend,
+};
+
+const PORTAL_DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SCREENSHOT_INTERFACE: &str = "org.freedesktop.portal.Screenshot";
+const REQUEST_INTERFACE: &str = "org.freedesktop.portal.Request";
+
+/// Captures through the `org.freedesktop.portal.Screenshot` D-Bus interface,
+/// for compositors without wlr-screencopy like KDE. The overlay still needs
+/// layer-shell, which GNOME lacks
+pub struct PortalBackend {
+    conn: Connection,
+}
+
+impl PortalBackend {
+    pub fn new(conn: Connection) -> Self {
+        Self { conn }
+    }
+
+    /// Take a non-interactive screenshot of the whole desktop
+    pub fn screenshot(&self) -> Result<DynamicImage, CaptureError> {
+        let screenshot = Proxy::new(
+            &self.conn,
+            PORTAL_DESTINATION,
+            PORTAL_PATH,
+            SCREENSHOT_INTERFACE,
+        )
+        .map_err(dbus_error)?;
+
+        // The request path is predictable from the handle token, which lets us
+        // subscribe to the response before making the call so it can't be missed
+        let token = format!("watershot{}", std::process::id());
+        let sender = self
+            .conn
+            .unique_name()
+            .ok_or(CaptureError::Failed(
+                "Not connected to a message bus".to_string(),
+            ))?
+            .trim_start_matches(':')
+            .replace('.', "_");
+        let request_path = format!("{}/request/{}/{}", PORTAL_PATH, sender, token);
+
+        let request = Proxy::new(
+            &self.conn,
+            PORTAL_DESTINATION,
+            request_path.as_str(),
+            REQUEST_INTERFACE,
+        )
+        .map_err(dbus_error)?;
+        let mut responses = request.receive_signal("Response").map_err(dbus_error)?;
+
+        let options = HashMap::from([
+            ("handle_token", Value::from(token.as_str())),
+            ("interactive", Value::from(false)),
+            ("modal", Value::from(false)),
+        ]);
+        let _: OwnedObjectPath = screenshot
+            .call("Screenshot", &("", options))
+            .map_err(dbus_error)?;
+
+        let response = responses.next().ok_or(CaptureError::Failed(
+            "The portal closed the request without responding".to_string(),
+        ))?;
+        let (code, results) = response.body().map_err(dbus_error)?;
+        let path = response_path(code, results)?;
+
+        let image = image::open(&path)
+            .map_err(|why| CaptureError::InvalidImage(format!("{}: {}", path.display(), why)))?;
+
+        // The portal saves every screenshot, usually into the pictures directory of the user
+        if let Err(why) = std::fs::remove_file(&path) {
+            log::warn!(
+                "Failed to remove the screenshot saved by the portal at {}: {}",
+                path.display(),
+                why
+            );
+        }
+
+        Ok(image)
+    }
+}
+
+impl CaptureBackend for PortalBackend {
+    fn capture_outputs(
+        &mut self,
+        outputs: &[(wl_output::WlOutput, OutputInfo)],
+    ) -> Result<Vec<CapturedOutput>, CaptureError> {
+        let rects = outputs
+            .iter()
+            .map(|(_, info)| {
+                logical_rect(info).ok_or(CaptureError::Failed(
+                    "Can't determine the geometry of an output".to_string(),
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The portal only offers a screenshot of the whole desktop
+        Ok(split(&self.screenshot()?, &rects))
+    }
+}
+
+impl InitializeCaptureBackend for PortalBackend {
+    fn try_new(_context: &CaptureContext) -> Result<Box<dyn CaptureBackend>, CaptureNotAvailable> {
+        let conn = Connection::session().map_err(|_| CaptureNotAvailable::Unsupported)?;
+
+        // Make sure there is something implementing the interface
+        Proxy::new(&conn, PORTAL_DESTINATION, PORTAL_PATH, SCREENSHOT_INTERFACE)
+            .and_then(|proxy| proxy.get_property::<u32>("version"))
+            .map_err(|_| CaptureNotAvailable::Unsupported)?;
+
+        Ok(Box::new(PortalBackend::new(conn)))
+    }
+}
+
+fn dbus_error(why: zbus::Error) -> CaptureError {
+    CaptureError::Failed(format!("D-Bus error: {}", why))
+}
+
+/// The path of the screenshot from the response code and results of the request
+fn response_path(
+    code: u32,
+    mut results: HashMap<String, OwnedValue>,
+) -> Result<PathBuf, CaptureError> {
+    match code {
+        0 => (),
+        1 => {
+            return Err(CaptureError::Failed(
+                "The screenshot was cancelled".to_string(),
+            ))
+        }
+        _ => {
+            return Err(CaptureError::Failed(
+                "The portal failed to take the screenshot".to_string(),
+            ))
+        }
+    }
+
+    let uri = results
+        .remove("uri")
+        .and_then(|uri| String::try_from(uri).ok())
+        .ok_or(CaptureError::InvalidImage(
+            "The portal response contains no URI".to_string(),
+        ))?;
+
+    uri_to_path(&uri).ok_or(CaptureError::InvalidImage(format!(
+        "Unsupported screenshot URI {}",
+        uri
+    )))
+}
+
+/// Decode a `file://` URI into a path
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    // Skip the host, usually empty or localhost
+    let path = &path[path.find('/')?..];
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+    };
+
+    use image::{Rgba, RgbaImage};
+    use zbus::{dbus_interface, MessageHeader};
+
+    use super::*;
+
+    /// A dbus-daemon serving a private session bus for the duration of a test
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        fn start() -> Option<Self> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.as_mut()?)
+                .read_line(&mut address)
+                .ok()?;
+
+            Some(Self {
+                daemon,
+                address: address.trim().to_string(),
+            })
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    struct MockScreenshot {
+        uri: String,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.portal.Screenshot")]
+    impl MockScreenshot {
+        async fn screenshot(
+            &self,
+            #[zbus(header)] header: MessageHeader<'_>,
+            #[zbus(connection)] conn: &zbus::Connection,
+            _parent_window: &str,
+            options: HashMap<String, OwnedValue>,
+        ) -> zbus::fdo::Result<OwnedObjectPath> {
+            let token = options
+                .get("handle_token")
+                .and_then(|token| String::try_from(token.clone()).ok())
+                .unwrap_or_default();
+            let sender = header.sender()?.unwrap().to_owned();
+            let path = format!(
+                "{}/request/{}/{}",
+                PORTAL_PATH,
+                sender.trim_start_matches(':').replace('.', "_"),
+                token
+            );
+
+            let results = HashMap::from([("uri", Value::from(self.uri.as_str()))]);
+            conn.emit_signal(
+                Some(sender),
+                path.as_str(),
+                REQUEST_INTERFACE,
+                "Response",
+                &(0u32, results),
+            )
+            .await?;
+
+            Ok(OwnedObjectPath::try_from(path).unwrap())
+        }
+
+        #[dbus_interface(property)]
+        fn version(&self) -> u32 {
+            2
+        }
+    }
+
+    #[test]
+    fn test_uri_to_path() {
+        assert_eq!(
+            uri_to_path("file:///tmp/Screenshot%20from%202023.png"),
+            Some(PathBuf::from("/tmp/Screenshot from 2023.png"))
+        );
+        assert_eq!(
+            uri_to_path("file://localhost/tmp/a.png"),
+            Some(PathBuf::from("/tmp/a.png"))
+        );
+        assert_eq!(uri_to_path("https://example.com/a.png"), None);
+    }
+
+    fn results(uri: Value) -> HashMap<String, OwnedValue> {
+        HashMap::from([("uri".to_string(), OwnedValue::from(uri))])
+    }
+
+    #[test]
+    fn test_response_path() {
+        assert_eq!(
+            response_path(0, results(Value::from("file:///tmp/a%20b.png"))).unwrap(),
+            PathBuf::from("/tmp/a b.png")
+        );
+
+        // Cancelled and failed requests
+        assert!(matches!(
+            response_path(1, HashMap::new()),
+            Err(CaptureError::Failed(_))
+        ));
+        assert!(matches!(
+            response_path(2, HashMap::new()),
+            Err(CaptureError::Failed(_))
+        ));
+
+        // No usable URI
+        assert!(matches!(
+            response_path(0, HashMap::new()),
+            Err(CaptureError::InvalidImage(_))
+        ));
+        assert!(matches!(
+            response_path(0, results(Value::from(1_u32))),
+            Err(CaptureError::InvalidImage(_))
+        ));
+        assert!(matches!(
+            response_path(0, results(Value::from("https://example.com/a.png"))),
+            Err(CaptureError::InvalidImage(_))
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn test_screenshot_from_mock_portal() {
+        let bus = PrivateBus::start().expect("Failed to start dbus-daemon");
+
+        let path =
+            std::env::temp_dir().join(format!("watershot {}-portal.png", std::process::id()));
+        RgbaImage::from_pixel(4, 2, Rgba([1, 2, 3, 255]))
+            .save(&path)
+            .unwrap();
+        let uri = format!("file://{}", path.to_string_lossy().replace(' ', "%20"));
+
+        let _portal = zbus::blocking::ConnectionBuilder::address(bus.address.as_str())
+            .unwrap()
+            .name(PORTAL_DESTINATION)
+            .unwrap()
+            .serve_at(PORTAL_PATH, MockScreenshot { uri })
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let conn = zbus::blocking::ConnectionBuilder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let image = PortalBackend::new(conn).screenshot().unwrap().to_rgba8();
+
+        assert_eq!((image.width(), image.height()), (4, 2));
+        assert_eq!(image.get_pixel(3, 1).0, [1, 2, 3, 255]);
+        // Not left behind for the user to clean up
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,279 @@
+use smithay_client_toolkit::{
+    error::GlobalError,
+    globals::ProvidesBoundGlobal,
+    output::OutputInfo,
+    reexports::{
+        client::{
+            protocol::{wl_buffer, wl_output, wl_shm},
+            Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+        },
+        protocols_wlr::screencopy::v1::client::{
+            zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+            zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+        },
+    },
+    shm::raw::RawPool,
+};
+
+use super::{
+    logical_rect,
+    shm::{apply_transform, is_supported_format, to_image, BufferInfo},
+    CaptureBackend, CaptureContext, CaptureError, CaptureNotAvailable, CapturedOutput,
+    InitializeCaptureBackend,
+};
+
+#[derive(Debug, PartialEq)]
+enum FrameStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+struct Frame {
+    frame: ZwlrScreencopyFrameV1,
+    /// The first buffer type offered by the compositor that we know how to read
+    buffer_info: Option<BufferInfo>,
+    /// Whether all the buffer types have been announced and the copy can be requested
+    buffer_done: bool,
+    y_invert: bool,
+    status: FrameStatus,
+    pool: Option<RawPool>,
+    buffer: Option<wl_buffer::WlBuffer>,
+}
+
+/// State of the dedicated event queue used while capturing
+struct ScreencopyState {
+    frames: Vec<Frame>,
+}
+
+/// Captures outputs with `zwlr_screencopy_manager_v1` on a dedicated event queue
+pub struct ScreencopyBackend {
+    event_queue: EventQueue<ScreencopyState>,
+    manager: ZwlrScreencopyManagerV1,
+    shm: wl_shm::WlShm,
+}
+
+impl CaptureBackend for ScreencopyBackend {
+    fn capture_outputs(
+        &mut self,
+        outputs: &[(wl_output::WlOutput, OutputInfo)],
+    ) -> Result<Vec<CapturedOutput>, CaptureError> {
+        let qh = self.event_queue.handle();
+
+        let mut state = ScreencopyState {
+            frames: outputs
+                .iter()
+                .enumerate()
+                .map(|(i, (output, _))| Frame {
+                    frame: self.manager.capture_output(0, output, &qh, i),
+                    buffer_info: None,
+                    buffer_done: false,
+                    y_invert: false,
+                    status: FrameStatus::Pending,
+                    pool: None,
+                    buffer: None,
+                })
+                .collect(),
+        };
+
+        while state
+            .frames
+            .iter()
+            .any(|frame| frame.status == FrameStatus::Pending)
+        {
+            self.event_queue
+                .blocking_dispatch(&mut state)
+                .map_err(|why| CaptureError::Failed(why.to_string()))?;
+
+            for frame in &mut state.frames {
+                if !frame.buffer_done
+                    || frame.buffer.is_some()
+                    || frame.status != FrameStatus::Pending
+                {
+                    continue;
+                }
+
+                let info = frame.buffer_info.ok_or(CaptureError::Failed(
+                    "No supported shm buffer format was offered".to_string(),
+                ))?;
+
+                let mut pool = RawPool::new((info.stride * info.height) as usize, self)
+                    .map_err(|why| CaptureError::Failed(why.to_string()))?;
+                let buffer = pool.create_buffer(
+                    0,
+                    info.width as i32,
+                    info.height as i32,
+                    info.stride as i32,
+                    info.format,
+                    (),
+                    &qh,
+                );
+
+                frame.frame.copy(&buffer);
+                frame.pool = Some(pool);
+                frame.buffer = Some(buffer);
+            }
+        }
+
+        let captured = state
+            .frames
+            .iter_mut()
+            .zip(outputs)
+            .map(|(frame, (_, info))| {
+                let name = info.name.as_deref().unwrap_or("<unknown>");
+
+                if frame.status == FrameStatus::Failed {
+                    return Err(CaptureError::Failed(format!(
+                        "Compositor failed to copy output {}",
+                        name
+                    )));
+                }
+
+                let rect = logical_rect(info).ok_or(CaptureError::Failed(format!(
+                    "Can't determine the geometry of output {}",
+                    name
+                )))?;
+
+                // Both are always set once the frame is ready
+                let buffer_info = frame.buffer_info.unwrap();
+                let pool = frame.pool.as_mut().unwrap();
+
+                let image = to_image(pool.mmap(), &buffer_info, frame.y_invert)?;
+
+                Ok(CapturedOutput {
+                    rect,
+                    image: apply_transform(image, info.transform),
+                })
+            })
+            .collect();
+
+        captured
+    }
+}
+
+impl InitializeCaptureBackend for ScreencopyBackend {
+    fn try_new(context: &CaptureContext) -> Result<Box<dyn CaptureBackend>, CaptureNotAvailable> {
+        let event_queue = context.conn.new_event_queue::<ScreencopyState>();
+        let qh = event_queue.handle();
+
+        let manager = context
+            .globals
+            .bind(&qh, 1..=3, ())
+            .map_err(|_| CaptureNotAvailable::Unsupported)?;
+        let shm = context
+            .globals
+            .bind(&qh, 1..=1, ())
+            .map_err(|_| CaptureNotAvailable::Unsupported)?;
+
+        Ok(Box::new(ScreencopyBackend {
+            event_queue,
+            manager,
+            shm,
+        }))
+    }
+}
+
+impl ProvidesBoundGlobal<wl_shm::WlShm, 1> for ScreencopyBackend {
+    fn bound_global(&self) -> Result<wl_shm::WlShm, GlobalError> {
+        Ok(self.shm.clone())
+    }
+}
+
+impl Drop for ScreencopyBackend {
+    fn drop(&mut self) {
+        self.manager.destroy();
+    }
+}
+
+/// Destroys the frames however the capture ends, the manager is destroyed with the backend
+impl Drop for ScreencopyState {
+    fn drop(&mut self) {
+        for frame in &self.frames {
+            if let Some(buffer) = &frame.buffer {
+                buffer.destroy();
+            }
+            frame.frame.destroy();
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyManagerV1, ()> for ScreencopyState {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrScreencopyManagerV1,
+        _: <ZwlrScreencopyManagerV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, usize> for ScreencopyState {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use zwlr_screencopy_frame_v1::Event;
+
+        let frame = &mut state.frames[*index];
+
+        match event {
+            Event::Buffer {
+                format: WEnum::Value(format),
+                width,
+                height,
+                stride,
+            } => {
+                if frame.buffer_info.is_none() && is_supported_format(format) {
+                    frame.buffer_info = Some(BufferInfo {
+                        format,
+                        width,
+                        height,
+                        stride,
+                    });
+                }
+
+                // Before version 3 there is only one buffer event and no buffer_done
+                if proxy.version() < 3 {
+                    frame.buffer_done = true;
+                }
+            }
+            Event::BufferDone => frame.buffer_done = true,
+            Event::Flags {
+                flags: WEnum::Value(flags),
+            } => frame.y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert),
+            Event::Ready { .. } => frame.status = FrameStatus::Ready,
+            Event::Failed => frame.status = FrameStatus::Failed,
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<wl_shm::WlShm, ()> for ScreencopyState {
+    fn event(
+        _: &mut Self,
+        _: &wl_shm::WlShm,
+        _: wl_shm::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_buffer::WlBuffer, ()> for ScreencopyState {
+    fn event(
+        _: &mut Self,
+        _: &wl_buffer::WlBuffer,
+        _: wl_buffer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
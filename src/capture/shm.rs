@@ -0,0 +1,109 @@
+use image::{DynamicImage, RgbaImage};
+use smithay_client_toolkit::reexports::client::protocol::{wl_output, wl_shm};
+
+use super::CaptureError;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BufferInfo {
+    pub format: wl_shm::Format,
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32,
+}
+
+/// Whether [`to_image`] knows how to read the format
+pub fn is_supported_format(format: wl_shm::Format) -> bool {
+    matches!(
+        format,
+        wl_shm::Format::Argb8888
+            | wl_shm::Format::Xrgb8888
+            | wl_shm::Format::Abgr8888
+            | wl_shm::Format::Xbgr8888
+    )
+}
+
+/// Convert the raw shm contents into an RGBA image
+pub fn to_image(
+    data: &[u8],
+    info: &BufferInfo,
+    y_invert: bool,
+) -> Result<DynamicImage, CaptureError> {
+    // Little endian, so the channel order in memory is reversed from the format name
+    let (swap_rb, opaque) = match info.format {
+        wl_shm::Format::Argb8888 => (true, false),
+        wl_shm::Format::Xrgb8888 => (true, true),
+        wl_shm::Format::Abgr8888 => (false, false),
+        wl_shm::Format::Xbgr8888 => (false, true),
+        format => {
+            return Err(CaptureError::InvalidImage(format!(
+                "Unsupported buffer format {:?}",
+                format
+            )))
+        }
+    };
+
+    let mut pixels = Vec::with_capacity((info.width * info.height * 4) as usize);
+
+    for y in 0..info.height {
+        let y = if y_invert { info.height - 1 - y } else { y };
+        let start = (y * info.stride) as usize;
+        let row = &data[start..start + (info.width * 4) as usize];
+
+        for pixel in row.chunks_exact(4) {
+            let (r, b) = if swap_rb {
+                (pixel[2], pixel[0])
+            } else {
+                (pixel[0], pixel[2])
+            };
+            let a = if opaque { 255 } else { pixel[3] };
+
+            pixels.extend_from_slice(&[r, pixel[1], b, a]);
+        }
+    }
+
+    RgbaImage::from_raw(info.width, info.height, pixels)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or(CaptureError::InvalidImage(
+            "Buffer is smaller than its announced size".to_string(),
+        ))
+}
+
+/// Undo the output transform so the image matches the logical layout of the output
+pub fn apply_transform(image: DynamicImage, transform: wl_output::Transform) -> DynamicImage {
+    use wl_output::Transform;
+
+    match transform {
+        Transform::_90 => image.rotate90(),
+        Transform::_180 => image.rotate180(),
+        Transform::_270 => image.rotate270(),
+        Transform::Flipped => image.fliph(),
+        Transform::Flipped90 => image.rotate90().fliph(),
+        Transform::Flipped180 => image.rotate180().fliph(),
+        Transform::Flipped270 => image.rotate270().fliph(),
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_image_channel_order_and_y_invert() {
+        // 1x2 Xrgb8888 buffer with padding in the stride, stored as BGRX
+        let data = [10, 20, 30, 0, 0, 0, 0, 0, 40, 50, 60, 0, 0, 0, 0, 0];
+        let info = BufferInfo {
+            format: wl_shm::Format::Xrgb8888,
+            width: 1,
+            height: 2,
+            stride: 8,
+        };
+
+        let image = to_image(&data, &info, false).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [30, 20, 10, 255]);
+        assert_eq!(image.get_pixel(0, 1).0, [60, 50, 40, 255]);
+
+        let image = to_image(&data, &info, true).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [60, 50, 40, 255]);
+    }
+}
@@ -0,0 +1,1452 @@
+use crate::{
+    annotation::{Annotation, AnnotationStyle, Shape, Tool},
+    handles,
+    traits::{Contains, DistanceTo},
+    types::{
+        AspectRatio, Config, DisplaySelection, ExitState, Extents, Rect, RectangleSelection,
+        Selection, SelectionModifier, SelectionState, Size,
+    },
+    window::{FindWindowExt, WindowDescriptor},
+};
+
+/// Input events, abstracted from the Wayland pointer and keyboard events
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    /// A pointer button was pressed, with the position in global coordinates
+    /// and the rect of the monitor it was pressed on
+    Press {
+        position: (i32, i32),
+        monitor: Rect<i32>,
+    },
+    /// The pointer entered a monitor at a position in global coordinates
+    Enter {
+        position: (i32, i32),
+    },
+    /// The pointer moved to a position in global coordinates
+    Motion {
+        position: (i32, i32),
+    },
+    /// A pointer button was released
+    Release,
+    Key(Key),
+    /// The held down modifier keys changed
+    Modifiers(Modifiers),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Escape,
+    Tab,
+    Return,
+    Up,
+    Down,
+    Left,
+    Right,
+    Backspace,
+    Delete,
+    /// Text typed with the key, or the letter of a shortcut while Ctrl is held
+    Text(String),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+/// What undo and redo restore
+#[derive(Clone, PartialEq)]
+struct Snapshot {
+    selection: Selection,
+    annotations: Vec<Annotation>,
+}
+
+/// The states of the selection and the annotations between the finished operations
+#[derive(Default)]
+struct History {
+    /// The state after the last finished operation
+    current: Option<Snapshot>,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+/// Turns the input events into changes of the selection and the exit state
+pub struct SelectionController {
+    pub selection: Selection,
+    pub exit: ExitState,
+    /// Combined area of all monitors
+    pub area: Rect<i32>,
+    pub handle_radius: i32,
+    pub windows: Vec<WindowDescriptor>,
+    /// Whether the compositor provides the windows for window mode
+    pub window_mode: bool,
+    /// Select the window under the pointer once it enters, for compositors
+    /// that can't report the cursor position up front
+    pub select_window_on_enter: bool,
+    /// Whether the window selected on enter is captured right away
+    pub auto_capture: bool,
+    /// What has been typed to search the window list
+    pub window_query: String,
+    /// The annotation tool in use, the pointer draws instead of changing the selection while set
+    pub tool: Option<Tool>,
+    pub annotations: Vec<Annotation>,
+    pub style: AnnotationStyle,
+    /// Whether the last annotation is still being drawn
+    drawing: bool,
+    /// Where the caret is in the text of the last annotation while it is typed
+    pub text_caret: Option<usize>,
+    pub modifiers: Modifiers,
+    history: History,
+    /// Where the pointer was last seen, in global coordinates
+    pub cursor: Option<(i32, i32)>,
+    /// Whether the magnifier is shown around the cursor
+    pub magnifier: bool,
+    /// The aspect ratio every drag of the selection keeps
+    pub aspect: Option<AspectRatio>,
+    /// The size of the selection in physical pixels, which then can only be moved
+    pub fixed_size: Option<Size>,
+    /// The rect of each monitor and the scale of its image, to turn the fixed size into
+    /// logical pixels on the monitor the selection is placed on
+    pub scales: Vec<(Rect<i32>, f32)>,
+    /// The ratio of the selection when it was grabbed, kept while Shift is held
+    grab_ratio: f32,
+}
+
+impl SelectionController {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            selection: Selection::default(),
+            exit: ExitState::None,
+            area: Rect::default(),
+            handle_radius: config.handle_radius,
+            windows: Vec::new(),
+            window_mode: false,
+            select_window_on_enter: false,
+            auto_capture: false,
+            window_query: String::new(),
+            tool: None,
+            annotations: Vec::new(),
+            style: AnnotationStyle::from(config),
+            drawing: false,
+            text_caret: None,
+            modifiers: Modifiers::default(),
+            history: History::default(),
+            cursor: None,
+            magnifier: false,
+            aspect: config.aspect,
+            fixed_size: config.size,
+            scales: Vec::new(),
+            grab_ratio: 1.0,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: InputEvent) {
+        // The initial selection is not something to undo
+        if self.history.current.is_none() {
+            self.history.current = Some(self.snapshot());
+        }
+
+        match event {
+            InputEvent::Press { position, monitor } => {
+                self.cursor = Some(position);
+                self.press(position, monitor);
+            }
+            InputEvent::Enter { position } => {
+                self.cursor = Some(position);
+                if std::mem::take(&mut self.select_window_on_enter) {
+                    self.select_window_at(position);
+                }
+            }
+            InputEvent::Motion { position } => {
+                self.cursor = Some(position);
+                self.motion(position);
+            }
+            InputEvent::Release => {
+                if self.drawing {
+                    self.finish_annotation();
+                } else if let Selection::Rectangle(Some(selection)) = &mut self.selection {
+                    selection.active = false;
+                }
+            }
+            InputEvent::Key(key) => self.press_key(key),
+            InputEvent::Modifiers(modifiers) => self.modifiers = modifiers,
+        }
+
+        self.record();
+    }
+
+    /// Stands in for the initial selection, so it is not something to undo either
+    fn select_window_at(&mut self, position: (i32, i32)) {
+        self.selection = Selection::from_window(self.windows.find_by_position(&position).cloned());
+        self.history.current = Some(self.snapshot());
+
+        if self.auto_capture {
+            self.exit_with_selected_rect();
+        }
+    }
+
+    /// Exit right away with the initial selection for `--auto-capture`, once the area is known
+    pub fn capture_initial_selection(&mut self) {
+        // TODO: Auto-capture for monitors
+        if let Selection::Rectangle(Some(_)) = self.selection.flattened() {
+            self.exit_with_selected_rect();
+        }
+    }
+
+    fn exit_with_selected_rect(&mut self) {
+        if let Some(mut rect) = self.selected_rect() {
+            // Alter coordinate space so the rect can be used to crop from the original image
+            rect.x -= self.area.x;
+            rect.y -= self.area.y;
+
+            self.exit = ExitState::ExitWithSelection(rect)
+        }
+    }
+
+    /// Forget the windows, for when they can't be captured after all
+    pub fn disable_window_mode(&mut self) {
+        self.window_mode = false;
+        self.windows.clear();
+        self.select_window_on_enter = false;
+        if let Selection::Window(_) = self.selection {
+            self.selection = Selection::default();
+        }
+    }
+
+    /// Whether window mode has to pick windows from a searchable list as
+    /// the compositor doesn't tell where they are
+    pub fn window_list_mode(&self) -> bool {
+        !self.windows.is_empty() && self.windows.iter().all(|window| window.rect.is_none())
+    }
+
+    /// The windows in the list matching the current query
+    pub fn window_list(&self) -> Vec<&WindowDescriptor> {
+        self.windows.filter_by_query(&self.window_query)
+    }
+
+    /// The selected region in global coordinates, if there is one
+    pub fn selected_rect(&self) -> Option<Rect<i32>> {
+        match self.selection.flattened() {
+            Selection::Rectangle(Some(selection)) => Some(selection.extents.to_rect()),
+            Selection::Display(Some(selection)) => Some(selection.rect),
+            _ => None,
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let mut selection = self.selection.clone();
+        // Only what is selected matters, not how it was being dragged
+        if let Selection::Rectangle(Some(selection)) = &mut selection {
+            selection.modifier = None;
+            selection.active = false;
+        }
+
+        Snapshot {
+            selection,
+            annotations: self.annotations.clone(),
+        }
+    }
+
+    /// Whether the selection or an annotation is still being changed
+    fn in_operation(&self) -> bool {
+        self.drawing
+            || self.text_caret.is_some()
+            || matches!(&self.selection, Selection::Rectangle(Some(selection)) if selection.active)
+    }
+
+    /// Add a finished operation that changed something to the history
+    fn record(&mut self) {
+        if self.in_operation() {
+            return;
+        }
+
+        let snapshot = self.snapshot();
+        if let Some(previous) = self.history.current.replace(snapshot.clone()) {
+            if previous != snapshot {
+                self.history.undo.push(previous);
+                self.history.redo.clear();
+            }
+        }
+    }
+
+    fn undo(&mut self) {
+        self.finish_operation();
+        if let Some(snapshot) = self.history.undo.pop() {
+            let current = self.history.current.replace(snapshot.clone());
+            self.history.redo.extend(current);
+            self.restore(snapshot);
+        }
+    }
+
+    fn redo(&mut self) {
+        self.finish_operation();
+        if let Some(snapshot) = self.history.redo.pop() {
+            let current = self.history.current.replace(snapshot.clone());
+            self.history.undo.extend(current);
+            self.restore(snapshot);
+        }
+    }
+
+    /// Finish what is being changed so it can be undone as a whole
+    fn finish_operation(&mut self) {
+        self.finish_annotation();
+        if let Selection::Rectangle(Some(selection)) = &mut self.selection {
+            selection.active = false;
+        }
+        self.record();
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.selection = snapshot.selection;
+        self.annotations = snapshot.annotations;
+        // The tools only draw on a selection
+        if self.selected_rect().is_none() {
+            self.tool = None;
+        }
+    }
+
+    fn finish_annotation(&mut self) {
+        self.drawing = false;
+        self.text_caret = None;
+        if self
+            .annotations
+            .last()
+            .is_some_and(|annotation| annotation.shape.is_empty())
+        {
+            self.annotations.pop();
+        }
+    }
+
+    fn press(&mut self, position: (i32, i32), monitor: Rect<i32>) {
+        if let Some(tool) = self.tool {
+            self.finish_annotation();
+            self.annotations.push(self.style.annotation(tool, position));
+            if tool == Tool::Text {
+                self.text_caret = Some(0);
+            } else {
+                self.drawing = true;
+            }
+            return;
+        }
+
+        if let Some(size) = self.fixed_size {
+            let placed = self.fixed_selection(size, position);
+            // Only moved, from anywhere within or by placing it on the pointer
+            if let Selection::Rectangle(selection) = &mut self.selection {
+                match selection {
+                    Some(selection) if selection.extents.to_rect().contains(&position) => {
+                        selection.modifier = Some(SelectionModifier::Center(
+                            position.0,
+                            position.1,
+                            selection.extents,
+                        ));
+                        selection.active = true;
+                    }
+                    _ => *selection = Some(placed),
+                }
+                return;
+            }
+        }
+
+        match &mut self.selection {
+            Selection::Rectangle(ref mut selection) => {
+                let handles_state =
+                    Self::process_selection_handles(selection, position, self.handle_radius);
+                if let SelectionState::Unchanged = handles_state {
+                    self.selection =
+                        Selection::Rectangle(Some(RectangleSelection::new(position.0, position.1)));
+                }
+            }
+            Selection::Display(_) => {
+                self.selection = Selection::Display(Some(DisplaySelection::new(monitor)));
+            }
+            Selection::Window(_) => {
+                let mut flattened_selection = self.selection.flattened();
+                if let Selection::Rectangle(ref mut rect_sel) = flattened_selection {
+                    let handles_state = if self.fixed_size.is_some() {
+                        SelectionState::Unchanged
+                    } else {
+                        Self::process_selection_handles(rect_sel, position, self.handle_radius)
+                    };
+                    if let SelectionState::HandlesChanged = handles_state {
+                        self.selection = flattened_selection;
+                    } else {
+                        let win_sel = self.windows.find_by_position(&position).cloned();
+
+                        if win_sel.is_some() {
+                            self.selection = Selection::Window(win_sel);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Selection::Rectangle(Some(selection)) = &self.selection {
+            let rect = selection.extents.to_rect();
+            self.grab_ratio = if rect.width > 0 && rect.height > 0 {
+                rect.width as f32 / rect.height as f32
+            } else {
+                1.0
+            };
+        }
+    }
+
+    /// A selection of the fixed size centered on the position, grabbed at its center
+    fn fixed_selection(&self, size: Size, position: (i32, i32)) -> RectangleSelection {
+        let scale = self
+            .scales
+            .iter()
+            .find(|(rect, _)| rect.contains(&position))
+            .map_or(1.0, |(_, scale)| *scale);
+        // Rounded up so fractional scales don't crop a pixel short, the capture is trimmed instead
+        let width = ((size.width as f32 / scale).ceil() as i32).min(self.area.width);
+        let height = ((size.height as f32 / scale).ceil() as i32).min(self.area.height);
+        let extents = Rect::new(
+            position.0 - width / 2,
+            position.1 - height / 2,
+            width,
+            height,
+        )
+        .to_extents()
+        .to_rect_clamped(&self.area)
+        .to_extents();
+
+        RectangleSelection {
+            extents,
+            modifier: Some(SelectionModifier::Center(position.0, position.1, extents)),
+            active: true,
+        }
+    }
+
+    fn motion(&mut self, position: (i32, i32)) {
+        if self.drawing {
+            if let Some(annotation) = self.annotations.last_mut() {
+                annotation.shape.drag_to(position);
+            }
+            return;
+        }
+
+        let ratio = match self.aspect {
+            Some(aspect) => Some(aspect.ratio()),
+            None => self.modifiers.shift.then_some(self.grab_ratio),
+        };
+
+        let Selection::Rectangle(Some(selection)) = &mut self.selection else {
+            return;
+        };
+        if !selection.active {
+            return;
+        }
+
+        match selection.modifier {
+            // Handle selection modifiers, AKA the drag handles and moving it from the center
+            Some(modifier) => match modifier {
+                SelectionModifier::Left => selection.extents.start_x = position.0,
+                SelectionModifier::Right => selection.extents.end_x = position.0,
+                SelectionModifier::Top => selection.extents.start_y = position.1,
+                SelectionModifier::Bottom => selection.extents.end_y = position.1,
+                SelectionModifier::TopRight => {
+                    selection.extents.end_x = position.0;
+                    selection.extents.start_y = position.1;
+                }
+                SelectionModifier::BottomRight => {
+                    selection.extents.end_x = position.0;
+                    selection.extents.end_y = position.1;
+                }
+                SelectionModifier::BottomLeft => {
+                    selection.extents.start_x = position.0;
+                    selection.extents.end_y = position.1;
+                }
+                SelectionModifier::TopLeft => {
+                    selection.extents.start_x = position.0;
+                    selection.extents.start_y = position.1;
+                }
+                SelectionModifier::Center(x, y, mut extents) => {
+                    extents.start_x -= x - position.0;
+                    extents.start_y -= y - position.1;
+                    extents.end_x -= x - position.0;
+                    extents.end_y -= y - position.1;
+
+                    selection.extents = extents.to_rect_clamped(&self.area).to_extents();
+                }
+            },
+            None => {
+                selection.extents.end_x = position.0;
+                selection.extents.end_y = position.1;
+            }
+        }
+
+        if let Some(ratio) = ratio {
+            keep_ratio(&mut selection.extents, selection.modifier, ratio);
+        }
+    }
+
+    fn press_key(&mut self, key: Key) {
+        // Shortcuts never type anything
+        if let (Key::Text(text), true) = (&key, self.modifiers.ctrl) {
+            match text.as_str() {
+                "z" if self.modifiers.shift => self.redo(),
+                "z" => self.undo(),
+                _ => (),
+            }
+            return;
+        }
+
+        if let Some(caret) = self.text_caret {
+            self.edit_text(caret, key);
+            return;
+        }
+
+        if let Selection::Window(_) = self.selection {
+            if self.window_list_mode() && self.process_window_list_key(&key) {
+                return;
+            }
+        }
+
+        match key {
+            // Put the tool away, exit without copying/saving if there is none
+            Key::Escape => {
+                if self.tool.is_some() {
+                    self.finish_annotation();
+                    self.tool = None;
+                } else {
+                    self.exit = ExitState::ExitOnly;
+                }
+            }
+            Key::Text(text) if text == "m" => self.magnifier = !self.magnifier,
+            // Start a selection at the pointer, the pointer then drags it until the next press
+            Key::Text(text) if text == " " && self.tool.is_none() => {
+                match (&mut self.selection, self.cursor) {
+                    (Selection::Rectangle(Some(selection)), _) if selection.active => {
+                        selection.active = false;
+                    }
+                    (Selection::Rectangle(_), Some(cursor)) => {
+                        let selection = match self.fixed_size {
+                            Some(size) => self.fixed_selection(size, cursor),
+                            None => RectangleSelection::new(cursor.0, cursor.1),
+                        };
+                        self.selection = Selection::Rectangle(Some(selection));
+                        self.grab_ratio = 1.0;
+                    }
+                    _ => (),
+                }
+            }
+            Key::Text(text) => {
+                if let Some(tool) = Tool::from_key(&text).filter(|_| self.selected_rect().is_some())
+                {
+                    self.finish_annotation();
+                    self.tool = Some(tool);
+                }
+            }
+            // Switch selection mode
+            Key::Tab if self.tool.is_some() => (),
+            Key::Tab => match &self.selection {
+                Selection::Rectangle(_) => self.selection = Selection::Display(None),
+                Selection::Display(_) => {
+                    if self.window_mode {
+                        self.window_query.clear();
+                        self.selection = if self.window_list_mode() {
+                            Selection::Window(self.window_list().first().cloned().cloned())
+                        } else {
+                            Selection::Window(None)
+                        };
+                    } else {
+                        self.selection = Selection::Rectangle(None)
+                    }
+                }
+                Selection::Window(_) => self.selection = Selection::Rectangle(None),
+            },
+            Key::Left | Key::Right | Key::Up | Key::Down => self.nudge(&key),
+            // Exit with save if a valid selection exists
+            Key::Return => {
+                if let Selection::Window(Some(WindowDescriptor { rect: None, .. })) = self.selection
+                {
+                    self.exit = ExitState::ExitWithWindow;
+                    return;
+                }
+
+                self.exit_with_selected_rect();
+            }
+            _ => (),
+        }
+    }
+
+    /// Move the selection with the arrow keys, or move one of its edges out with Ctrl
+    /// or in with Alt. Shift moves ten pixels at a time instead of one.
+    fn nudge(&mut self, key: &Key) {
+        // Windows turn into a rectangle selection like when dragging their handles
+        let Selection::Rectangle(Some(mut selection)) = self.selection.flattened() else {
+            return;
+        };
+
+        let step = if self.modifiers.shift { 10 } else { 1 };
+        let (dx, dy) = match key {
+            Key::Left => (-step, 0),
+            Key::Right => (step, 0),
+            Key::Up => (0, -step),
+            Key::Down => (0, step),
+            _ => return,
+        };
+
+        let rect = selection.extents.to_rect();
+        let (mut left, mut top) = (rect.x, rect.y);
+        let (mut right, mut bottom) = (rect.x + rect.width, rect.y + rect.height);
+
+        if self.modifiers.ctrl || self.modifiers.alt {
+            if self.fixed_size.is_some() {
+                return;
+            }
+
+            // The edge the arrow points to, moved outwards with Ctrl and inwards with Alt
+            let (dx, dy) = if self.modifiers.ctrl {
+                (dx, dy)
+            } else {
+                (-dx, -dy)
+            };
+            match key {
+                Key::Left => left = (left + dx).clamp(self.area.x, right),
+                Key::Right => right = (right + dx).clamp(left, self.area.x + self.area.width),
+                Key::Up => top = (top + dy).clamp(self.area.y, bottom),
+                _ => bottom = (bottom + dy).clamp(top, self.area.y + self.area.height),
+            }
+            selection.extents = Rect::new(left, top, right - left, bottom - top).to_extents();
+
+            if let Some(aspect) = self.aspect {
+                let edge = match key {
+                    Key::Left => SelectionModifier::Left,
+                    Key::Right => SelectionModifier::Right,
+                    Key::Up => SelectionModifier::Top,
+                    _ => SelectionModifier::Bottom,
+                };
+                keep_ratio(&mut selection.extents, Some(edge), aspect.ratio());
+            }
+        } else {
+            selection.extents.start_x += dx;
+            selection.extents.end_x += dx;
+            selection.extents.start_y += dy;
+            selection.extents.end_y += dy;
+            selection.extents = selection.extents.to_rect_clamped(&self.area).to_extents();
+        }
+
+        selection.modifier = None;
+        self.selection = Selection::Rectangle(Some(selection));
+    }
+
+    /// Type into the text annotation that is being written
+    fn edit_text(&mut self, caret: usize, key: Key) {
+        let Some(Annotation {
+            shape: Shape::Text { text, .. },
+            ..
+        }) = self.annotations.last_mut()
+        else {
+            self.text_caret = None;
+            return;
+        };
+
+        let previous = text[..caret]
+            .chars()
+            .next_back()
+            .map_or(caret, |c| caret - c.len_utf8());
+        let next = text[caret..]
+            .chars()
+            .next()
+            .map_or(caret, |c| caret + c.len_utf8());
+
+        self.text_caret = match key {
+            Key::Text(typed) => {
+                text.insert_str(caret, &typed);
+                Some(caret + typed.len())
+            }
+            Key::Backspace => {
+                text.replace_range(previous..caret, "");
+                Some(previous)
+            }
+            Key::Delete => {
+                text.replace_range(caret..next, "");
+                Some(caret)
+            }
+            Key::Left => Some(previous),
+            Key::Right => Some(next),
+            Key::Return | Key::Escape => {
+                self.finish_annotation();
+                None
+            }
+            _ => Some(caret),
+        };
+    }
+
+    /// Search and move around the window list, returns whether the key was used
+    fn process_window_list_key(&mut self, key: &Key) -> bool {
+        let selected = match &self.selection {
+            Selection::Window(Some(selected)) => self
+                .window_list()
+                .iter()
+                .position(|window| *window == selected),
+            _ => None,
+        };
+
+        let index = match key {
+            Key::Up => selected.map_or(0, |selected| selected.saturating_sub(1)),
+            Key::Down => selected.map_or(0, |selected| selected + 1),
+            Key::Backspace => {
+                self.window_query.pop();
+                0
+            }
+            Key::Text(text) => {
+                self.window_query.push_str(text);
+                0
+            }
+            _ => return false,
+        };
+
+        let window_list = self.window_list();
+        let window = window_list
+            .get(index)
+            .or(window_list.last())
+            .map(|window| (*window).clone());
+        self.selection = Selection::Window(window);
+
+        true
+    }
+
+    fn process_selection_handles(
+        rect_sel: &mut Option<RectangleSelection>,
+        global_pos: (i32, i32),
+        handle_radius: i32,
+    ) -> SelectionState {
+        if let Some(selection) = rect_sel {
+            for (x, y, modifier) in handles!(selection.extents) {
+                if global_pos.distance_to(&(*x, *y)) <= handle_radius {
+                    selection.modifier = Some(*modifier);
+                    selection.active = true;
+                    return SelectionState::HandlesChanged;
+                }
+            }
+            if selection.extents.to_rect().contains(&global_pos) {
+                selection.modifier = Some(SelectionModifier::Center(
+                    global_pos.0,
+                    global_pos.1,
+                    selection.extents,
+                ));
+                selection.active = true;
+                return SelectionState::CenterChanged;
+            }
+        }
+
+        SelectionState::Unchanged
+    }
+}
+
+/// Change the extents to the ratio by moving what the modifier drags, with the opposite
+/// corner or edge staying where it is. Edges change the size of the other dimension.
+fn keep_ratio(extents: &mut Extents, modifier: Option<SelectionModifier>, ratio: f32) {
+    // Away from the anchor, with zero counting as positive
+    let span = |anchor: i32, length: f32, towards: i32| {
+        let length = length.round() as i32;
+        if towards < anchor {
+            anchor - length
+        } else {
+            anchor + length
+        }
+    };
+
+    let Extents {
+        start_x,
+        start_y,
+        end_x,
+        end_y,
+    } = *extents;
+    let width = (end_x - start_x).abs() as f32;
+    let height = (end_y - start_y).abs() as f32;
+
+    match modifier {
+        Some(SelectionModifier::Center(..)) => (),
+        Some(SelectionModifier::Left | SelectionModifier::Right) => {
+            extents.end_y = span(start_y, width / ratio, end_y);
+        }
+        Some(SelectionModifier::Top | SelectionModifier::Bottom) => {
+            extents.end_x = span(start_x, height * ratio, end_x);
+        }
+        Some(corner) => {
+            // Whichever of the width and height is larger decides the size
+            let (width, height) = if width > height * ratio {
+                (width, width / ratio)
+            } else {
+                (height * ratio, height)
+            };
+            let (x, y) = match corner {
+                SelectionModifier::TopLeft => (&mut extents.start_x, &mut extents.start_y),
+                SelectionModifier::TopRight => (&mut extents.end_x, &mut extents.start_y),
+                SelectionModifier::BottomLeft => (&mut extents.start_x, &mut extents.end_y),
+                _ => (&mut extents.end_x, &mut extents.end_y),
+            };
+            let (anchor_x, anchor_y) = match corner {
+                SelectionModifier::TopLeft => (end_x, end_y),
+                SelectionModifier::TopRight => (start_x, end_y),
+                SelectionModifier::BottomLeft => (end_x, start_y),
+                _ => (start_x, start_y),
+            };
+            *x = span(anchor_x, width, *x);
+            *y = span(anchor_y, height, *y);
+        }
+        // A new selection is dragged by its end
+        None => keep_ratio(extents, Some(SelectionModifier::BottomRight), ratio),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONITOR: Rect<i32> = Rect {
+        x: 0,
+        y: 0,
+        width: 1000,
+        height: 500,
+    };
+
+    fn controller() -> SelectionController {
+        let mut controller = SelectionController::new(&Config::default());
+        controller.area = MONITOR;
+        controller
+    }
+
+    fn press(controller: &mut SelectionController, x: i32, y: i32) {
+        controller.handle_event(InputEvent::Press {
+            position: (x, y),
+            monitor: MONITOR,
+        });
+    }
+
+    fn drag(controller: &mut SelectionController, from: (i32, i32), to: (i32, i32)) {
+        press(controller, from.0, from.1);
+        controller.handle_event(InputEvent::Motion { position: to });
+        controller.handle_event(InputEvent::Release);
+    }
+
+    fn selected_rect(controller: &SelectionController) -> Rect<i32> {
+        match controller.selection.flattened() {
+            Selection::Rectangle(Some(selection)) => selection.extents.to_rect(),
+            _ => panic!("No rectangle selected"),
+        }
+    }
+
+    fn window(title: &str, rect: Option<Rect<i32>>) -> WindowDescriptor {
+        WindowDescriptor {
+            initial_title: title.to_string(),
+            title: title.to_string(),
+            initial_class: String::new(),
+            class: String::new(),
+            rect,
+        }
+    }
+
+    #[test]
+    fn test_drag_new_selection() {
+        let mut controller = controller();
+        drag(&mut controller, (300, 200), (100, 50));
+
+        assert_eq!(selected_rect(&controller), Rect::new(100, 50, 200, 150));
+    }
+
+    #[test]
+    fn test_handle_drag() {
+        let mut controller = controller();
+        drag(&mut controller, (100, 100), (200, 200));
+
+        // Grab the bottom right corner from within the handle radius
+        drag(&mut controller, (205, 195), (300, 250));
+        assert_eq!(selected_rect(&controller), Rect::new(100, 100, 200, 150));
+
+        // The top edge handle only moves the top edge
+        drag(&mut controller, (200, 100), (500, 20));
+        assert_eq!(selected_rect(&controller), Rect::new(100, 20, 200, 230));
+    }
+
+    #[test]
+    fn test_center_move() {
+        let mut controller = controller();
+        drag(&mut controller, (100, 100), (200, 200));
+        drag(&mut controller, (150, 150), (170, 130));
+
+        assert_eq!(selected_rect(&controller), Rect::new(120, 80, 100, 100));
+    }
+
+    #[test]
+    fn test_center_move_is_clamped_to_area() {
+        let mut controller = controller();
+        drag(&mut controller, (100, 100), (200, 200));
+        drag(&mut controller, (150, 150), (2000, -500));
+
+        assert_eq!(selected_rect(&controller), Rect::new(900, 0, 100, 100));
+    }
+
+    #[test]
+    fn test_press_outside_starts_new_selection() {
+        let mut controller = controller();
+        drag(&mut controller, (100, 100), (200, 200));
+        drag(&mut controller, (400, 400), (450, 420));
+
+        assert_eq!(selected_rect(&controller), Rect::new(400, 400, 50, 20));
+    }
+
+    #[test]
+    fn test_mode_switching() {
+        let mut controller = controller();
+
+        controller.handle_event(InputEvent::Key(Key::Tab));
+        assert!(matches!(controller.selection, Selection::Display(None)));
+
+        // Window mode is skipped without a compositor backend
+        controller.handle_event(InputEvent::Key(Key::Tab));
+        assert!(matches!(controller.selection, Selection::Rectangle(None)));
+
+        controller.window_mode = true;
+        controller.windows = vec![window("window", Some(Rect::new(0, 0, 10, 10)))];
+        controller.handle_event(InputEvent::Key(Key::Tab));
+        controller.handle_event(InputEvent::Key(Key::Tab));
+        assert!(matches!(controller.selection, Selection::Window(None)));
+
+        controller.handle_event(InputEvent::Key(Key::Tab));
+        assert!(matches!(controller.selection, Selection::Rectangle(None)));
+    }
+
+    #[test]
+    fn test_exit_states() {
+        let mut controller = controller();
+        controller.area = Rect::new(-1000, 0, 2000, 500);
+
+        // Nothing to exit with yet
+        controller.handle_event(InputEvent::Key(Key::Return));
+        assert_eq!(controller.exit, ExitState::None);
+
+        // The rect is relative to the area
+        drag(&mut controller, (-100, 100), (100, 200));
+        controller.handle_event(InputEvent::Key(Key::Return));
+        assert_eq!(
+            controller.exit,
+            ExitState::ExitWithSelection(Rect::new(900, 100, 200, 100))
+        );
+
+        controller.handle_event(InputEvent::Key(Key::Escape));
+        assert_eq!(controller.exit, ExitState::ExitOnly);
+    }
+
+    #[test]
+    fn test_display_selection() {
+        let mut controller = controller();
+        controller.area = Rect::new(0, 0, 2000, 500);
+        controller.handle_event(InputEvent::Key(Key::Tab));
+
+        controller.handle_event(InputEvent::Press {
+            position: (1500, 10),
+            monitor: Rect::new(1000, 0, 1000, 500),
+        });
+        controller.handle_event(InputEvent::Key(Key::Return));
+        assert_eq!(
+            controller.exit,
+            ExitState::ExitWithSelection(Rect::new(1000, 0, 1000, 500))
+        );
+    }
+
+    #[test]
+    fn test_window_click_and_handle_drag() {
+        let mut controller = controller();
+        controller.window_mode = true;
+        controller.windows = vec![
+            window("top", Some(Rect::new(50, 50, 100, 100))),
+            window("bottom", Some(Rect::new(0, 0, 300, 300))),
+        ];
+        controller.handle_event(InputEvent::Key(Key::Tab));
+        controller.handle_event(InputEvent::Key(Key::Tab));
+
+        press(&mut controller, 100, 100);
+        assert!(
+            matches!(&controller.selection, Selection::Window(Some(window)) if window.title == "top")
+        );
+
+        // Dragging a handle of the window turns it into a rectangle selection
+        drag(&mut controller, (150, 150), (200, 250));
+        assert!(matches!(
+            controller.selection,
+            Selection::Rectangle(Some(_))
+        ));
+        assert_eq!(selected_rect(&controller), Rect::new(50, 50, 150, 200));
+    }
+
+    #[test]
+    fn test_select_window_on_enter() {
+        let mut controller = controller();
+        // The exit rect is relative to the area
+        controller.area = Rect::new(-500, -100, 1500, 600);
+        controller.window_mode = true;
+        controller.windows = vec![
+            window("top", Some(Rect::new(50, 50, 100, 100))),
+            window("bottom", Some(Rect::new(0, 0, 300, 300))),
+        ];
+        controller.select_window_on_enter = true;
+        controller.auto_capture = true;
+
+        controller.handle_event(InputEvent::Enter {
+            position: (100, 100),
+        });
+        assert!(
+            matches!(&controller.selection, Selection::Window(Some(window)) if window.title == "top")
+        );
+        assert_eq!(
+            controller.exit,
+            ExitState::ExitWithSelection(Rect::new(550, 150, 100, 100))
+        );
+
+        // Only the first time
+        controller.handle_event(InputEvent::Enter {
+            position: (250, 250),
+        });
+        assert!(
+            matches!(&controller.selection, Selection::Window(Some(window)) if window.title == "top")
+        );
+    }
+
+    #[test]
+    fn test_capture_initial_selection() {
+        let mut controller = controller();
+        controller.area = Rect::new(-500, -100, 1500, 600);
+        controller.capture_initial_selection();
+        assert_eq!(controller.exit, ExitState::None);
+
+        controller.selection =
+            Selection::from_window(Some(window("found", Some(Rect::new(-400, 0, 200, 100)))));
+        controller.capture_initial_selection();
+        assert_eq!(
+            controller.exit,
+            ExitState::ExitWithSelection(Rect::new(100, 100, 200, 100))
+        );
+    }
+
+    #[test]
+    fn test_window_list_search() {
+        let mut controller = controller();
+        controller.window_mode = true;
+        controller.windows = vec![
+            window("Terminal", None),
+            window("Browser", None),
+            window("Text editor", None),
+        ];
+
+        controller.handle_event(InputEvent::Key(Key::Tab));
+        controller.handle_event(InputEvent::Key(Key::Tab));
+        assert!(
+            matches!(&controller.selection, Selection::Window(Some(window)) if window.title == "Terminal")
+        );
+
+        for text in ["t", "e"] {
+            controller.handle_event(InputEvent::Key(Key::Text(text.to_string())));
+        }
+        controller.handle_event(InputEvent::Key(Key::Down));
+        assert!(
+            matches!(&controller.selection, Selection::Window(Some(window)) if window.title == "Text editor")
+        );
+
+        // Clicking doesn't pick windows without geometry
+        press(&mut controller, 10, 10);
+        assert!(
+            matches!(&controller.selection, Selection::Window(Some(window)) if window.title == "Text editor")
+        );
+
+        controller.handle_event(InputEvent::Key(Key::Return));
+        assert_eq!(controller.exit, ExitState::ExitWithWindow);
+    }
+
+    #[test]
+    fn test_no_window_list_without_windows() {
+        let mut controller = controller();
+        assert!(!controller.window_list_mode());
+
+        controller.windows = vec![window("Terminal", None)];
+        assert!(controller.window_list_mode());
+
+        controller.windows.push(window("Browser", Some(MONITOR)));
+        assert!(!controller.window_list_mode());
+    }
+
+    #[test]
+    fn test_annotation_tools() {
+        let mut controller = controller();
+
+        // Tools need a selection to draw on
+        controller.handle_event(InputEvent::Key(Key::Text("a".to_string())));
+        assert_eq!(controller.tool, None);
+
+        drag(&mut controller, (100, 100), (200, 200));
+        controller.handle_event(InputEvent::Key(Key::Text("a".to_string())));
+        assert_eq!(controller.tool, Some(Tool::Arrow));
+
+        // Drawing leaves the selection alone
+        drag(&mut controller, (150, 150), (120, 110));
+        assert_eq!(selected_rect(&controller), Rect::new(100, 100, 100, 100));
+        assert_eq!(
+            controller.annotations[0].shape,
+            Shape::Arrow {
+                start: (150, 150),
+                end: (120, 110)
+            }
+        );
+
+        // Clicking without dragging draws nothing
+        controller.handle_event(InputEvent::Key(Key::Text("r".to_string())));
+        drag(&mut controller, (130, 130), (130, 130));
+        assert_eq!(controller.annotations.len(), 1);
+
+        // The first escape only puts the tool away
+        controller.handle_event(InputEvent::Key(Key::Escape));
+        assert_eq!(controller.tool, None);
+        assert_eq!(controller.exit, ExitState::None);
+
+        drag(&mut controller, (150, 150), (160, 160));
+        assert_eq!(selected_rect(&controller), Rect::new(110, 110, 100, 100));
+        assert_eq!(controller.annotations.len(), 1);
+    }
+
+    fn type_text(controller: &mut SelectionController, text: &str) {
+        for c in text.chars() {
+            controller.handle_event(InputEvent::Key(Key::Text(c.to_string())));
+        }
+    }
+
+    #[test]
+    fn test_text_editing() {
+        let mut controller = controller();
+        drag(&mut controller, (100, 100), (200, 200));
+        controller.handle_event(InputEvent::Key(Key::Text("t".to_string())));
+
+        press(&mut controller, 120, 130);
+        controller.handle_event(InputEvent::Release);
+        // Tool keys are typed as text while writing
+        type_text(&mut controller, "täxt");
+        controller.handle_event(InputEvent::Key(Key::Left));
+        controller.handle_event(InputEvent::Key(Key::Left));
+        controller.handle_event(InputEvent::Key(Key::Backspace));
+        type_text(&mut controller, "e");
+        controller.handle_event(InputEvent::Key(Key::Right));
+        controller.handle_event(InputEvent::Key(Key::Delete));
+        type_text(&mut controller, "!");
+
+        assert_eq!(
+            controller.annotations[0].shape,
+            Shape::Text {
+                position: (120, 130),
+                text: "tex!".to_string()
+            }
+        );
+
+        // Return finishes the text instead of exiting
+        controller.handle_event(InputEvent::Key(Key::Return));
+        assert_eq!(controller.text_caret, None);
+        assert_eq!(controller.exit, ExitState::None);
+
+        // Placing the caret without typing leaves nothing behind
+        press(&mut controller, 150, 150);
+        press(&mut controller, 160, 160);
+        controller.handle_event(InputEvent::Key(Key::Escape));
+        assert_eq!(controller.annotations.len(), 1);
+        assert_eq!(controller.tool, Some(Tool::Text));
+    }
+
+    fn shortcut(controller: &mut SelectionController, shift: bool) {
+        let modifiers = Modifiers {
+            ctrl: true,
+            shift,
+            alt: false,
+        };
+        controller.handle_event(InputEvent::Modifiers(modifiers));
+        controller.handle_event(InputEvent::Key(Key::Text("z".to_string())));
+        controller.handle_event(InputEvent::Modifiers(Modifiers::default()));
+    }
+
+    #[test]
+    fn test_undo_selection_changes() {
+        let mut controller = controller();
+        drag(&mut controller, (100, 100), (200, 200));
+        drag(&mut controller, (300, 300), (400, 450));
+        // Moving the selection around is a single change
+        press(&mut controller, 350, 350);
+        for x in 351..360 {
+            controller.handle_event(InputEvent::Motion { position: (x, 350) });
+        }
+        controller.handle_event(InputEvent::Release);
+
+        shortcut(&mut controller, false);
+        assert_eq!(selected_rect(&controller), Rect::new(300, 300, 100, 150));
+        shortcut(&mut controller, false);
+        assert_eq!(selected_rect(&controller), Rect::new(100, 100, 100, 100));
+        shortcut(&mut controller, false);
+        assert!(matches!(controller.selection, Selection::Rectangle(None)));
+        // Nothing left to undo
+        shortcut(&mut controller, false);
+        assert!(matches!(controller.selection, Selection::Rectangle(None)));
+
+        shortcut(&mut controller, true);
+        shortcut(&mut controller, true);
+        assert_eq!(selected_rect(&controller), Rect::new(300, 300, 100, 150));
+
+        // Switching the mode can be undone too
+        controller.handle_event(InputEvent::Key(Key::Tab));
+        shortcut(&mut controller, false);
+        assert_eq!(selected_rect(&controller), Rect::new(300, 300, 100, 150));
+    }
+
+    #[test]
+    fn test_undo_annotations() {
+        let mut controller = controller();
+        drag(&mut controller, (100, 100), (200, 200));
+        controller.handle_event(InputEvent::Key(Key::Text("l".to_string())));
+        drag(&mut controller, (110, 110), (120, 120));
+        drag(&mut controller, (130, 130), (140, 140));
+
+        // The text is undone as a whole, even while it is being typed
+        controller.handle_event(InputEvent::Key(Key::Text("t".to_string())));
+        press(&mut controller, 150, 150);
+        type_text(&mut controller, "text");
+        shortcut(&mut controller, false);
+        assert_eq!(controller.annotations.len(), 2);
+        assert_eq!(controller.text_caret, None);
+
+        shortcut(&mut controller, false);
+        assert_eq!(controller.annotations.len(), 1);
+        shortcut(&mut controller, true);
+        assert_eq!(controller.annotations.len(), 2);
+
+        // Something new can't be redone past
+        shortcut(&mut controller, false);
+        controller.handle_event(InputEvent::Key(Key::Text("l".to_string())));
+        drag(&mut controller, (160, 160), (170, 170));
+        shortcut(&mut controller, true);
+        assert_eq!(controller.annotations.len(), 2);
+        assert_eq!(
+            controller.annotations[1].shape,
+            Shape::Line {
+                start: (160, 160),
+                end: (170, 170)
+            }
+        );
+
+        // Undoing the selection puts the tool away
+        for _ in 0..3 {
+            shortcut(&mut controller, false);
+        }
+        assert!(controller.annotations.is_empty());
+        assert_eq!(controller.tool, None);
+    }
+
+    #[test]
+    fn test_counters_renumber_on_undo() {
+        let mut controller = controller();
+        drag(&mut controller, (100, 100), (200, 200));
+        controller.handle_event(InputEvent::Key(Key::Text("n".to_string())));
+        drag(&mut controller, (110, 110), (120, 120));
+        drag(&mut controller, (150, 150), (150, 150));
+
+        shortcut(&mut controller, false);
+        drag(&mut controller, (180, 180), (180, 180));
+
+        // Dragging places the counter
+        assert_eq!(
+            controller.annotations[0].shape,
+            Shape::Counter { center: (120, 120) }
+        );
+        assert_eq!(
+            controller.annotations[1].shape,
+            Shape::Counter { center: (180, 180) }
+        );
+        assert_eq!(
+            crate::annotation::counter_number(&controller.annotations, 1),
+            2
+        );
+    }
+
+    #[test]
+    fn test_magnifier_toggle() {
+        let mut controller = controller();
+        controller.handle_event(InputEvent::Motion { position: (30, 40) });
+        assert_eq!(controller.cursor, Some((30, 40)));
+
+        controller.handle_event(InputEvent::Key(Key::Text("m".to_string())));
+        assert!(controller.magnifier);
+
+        // Typed into the text instead while writing
+        drag(&mut controller, (100, 100), (200, 200));
+        controller.handle_event(InputEvent::Key(Key::Text("t".to_string())));
+        press(&mut controller, 120, 120);
+        type_text(&mut controller, "m");
+        assert!(controller.magnifier);
+
+        controller.handle_event(InputEvent::Key(Key::Return));
+        controller.handle_event(InputEvent::Key(Key::Text("m".to_string())));
+        assert!(!controller.magnifier);
+    }
+
+    fn key_with(controller: &mut SelectionController, key: Key, modifiers: Modifiers) {
+        controller.handle_event(InputEvent::Modifiers(modifiers));
+        controller.handle_event(InputEvent::Key(key));
+        controller.handle_event(InputEvent::Modifiers(Modifiers::default()));
+    }
+
+    #[test]
+    fn test_arrow_keys() {
+        let mut controller = controller();
+        drag(&mut controller, (200, 200), (100, 100));
+
+        controller.handle_event(InputEvent::Key(Key::Right));
+        let shift = Modifiers {
+            shift: true,
+            ..Default::default()
+        };
+        key_with(&mut controller, Key::Up, shift);
+        assert_eq!(selected_rect(&controller), Rect::new(101, 90, 100, 100));
+
+        let ctrl = Modifiers {
+            ctrl: true,
+            ..Default::default()
+        };
+        key_with(&mut controller, Key::Left, ctrl);
+        key_with(&mut controller, Key::Down, ctrl);
+        assert_eq!(selected_rect(&controller), Rect::new(100, 90, 101, 101));
+
+        let alt = Modifiers {
+            alt: true,
+            ..Default::default()
+        };
+        key_with(&mut controller, Key::Right, alt);
+        key_with(&mut controller, Key::Up, Modifiers { shift: true, ..alt });
+        assert_eq!(selected_rect(&controller), Rect::new(100, 100, 100, 91));
+
+        // Kept within the area
+        for _ in 0..20 {
+            key_with(&mut controller, Key::Left, shift);
+        }
+        assert_eq!(selected_rect(&controller), Rect::new(0, 100, 100, 91));
+        key_with(&mut controller, Key::Left, ctrl);
+        assert_eq!(selected_rect(&controller), Rect::new(0, 100, 100, 91));
+    }
+
+    #[test]
+    fn test_aspect_ratio() {
+        let mut controller = controller();
+        controller.aspect = Some(AspectRatio {
+            width: 16,
+            height: 9,
+        });
+
+        // The larger side decides the size
+        drag(&mut controller, (100, 100), (420, 150));
+        assert_eq!(selected_rect(&controller), Rect::new(100, 100, 320, 180));
+        drag(&mut controller, (100, 100), (50, 80));
+        assert_eq!(selected_rect(&controller), Rect::new(50, 72, 370, 208));
+
+        // Edges change the other dimension along with them
+        let mut controller = controller_with_selection();
+        controller.aspect = Some(AspectRatio {
+            width: 2,
+            height: 1,
+        });
+        drag(&mut controller, (200, 150), (300, 150));
+        assert_eq!(selected_rect(&controller), Rect::new(100, 100, 200, 100));
+    }
+
+    fn controller_with_selection() -> SelectionController {
+        let mut controller = controller();
+        drag(&mut controller, (100, 100), (200, 200));
+        controller
+    }
+
+    #[test]
+    fn test_shift_keeps_ratio() {
+        let mut controller = controller_with_selection();
+        controller.handle_event(InputEvent::Modifiers(Modifiers {
+            shift: true,
+            ..Default::default()
+        }));
+
+        // Grabbed as a square, stays one
+        drag(&mut controller, (200, 200), (300, 220));
+        assert_eq!(selected_rect(&controller), Rect::new(100, 100, 200, 200));
+        drag(&mut controller, (100, 100), (150, 50));
+        assert_eq!(selected_rect(&controller), Rect::new(50, 50, 250, 250));
+        drag(&mut controller, (400, 400), (450, 410));
+        assert_eq!(selected_rect(&controller), Rect::new(400, 400, 50, 50));
+
+        controller.handle_event(InputEvent::Modifiers(Modifiers::default()));
+        drag(&mut controller, (450, 450), (500, 460));
+        assert_eq!(selected_rect(&controller), Rect::new(400, 400, 100, 60));
+    }
+
+    #[test]
+    fn test_fixed_size() {
+        let mut controller = controller();
+        controller.fixed_size = Some(Size {
+            width: 200,
+            height: 100,
+        });
+
+        // Placed centered on the pointer and moved by dragging
+        drag(&mut controller, (300, 300), (310, 320));
+        assert_eq!(selected_rect(&controller), Rect::new(210, 270, 200, 100));
+
+        // The handles only move it
+        drag(&mut controller, (410, 370), (500, 400));
+        assert_eq!(selected_rect(&controller), Rect::new(300, 300, 200, 100));
+
+        // Kept within the area
+        drag(&mut controller, (10, 10), (0, 0));
+        assert_eq!(selected_rect(&controller), Rect::new(0, 0, 200, 100));
+
+        let ctrl = Modifiers {
+            ctrl: true,
+            ..Default::default()
+        };
+        key_with(&mut controller, Key::Right, ctrl);
+        controller.handle_event(InputEvent::Key(Key::Right));
+        assert_eq!(selected_rect(&controller), Rect::new(1, 0, 200, 100));
+    }
+
+    #[test]
+    fn test_fixed_size_in_physical_pixels() {
+        let mut controller = controller();
+        controller.fixed_size = Some(Size {
+            width: 200,
+            height: 100,
+        });
+        controller.scales = vec![
+            (Rect::new(0, 0, 500, 500), 2.0),
+            (Rect::new(500, 0, 500, 500), 1.5),
+        ];
+
+        drag(&mut controller, (200, 200), (200, 200));
+        assert_eq!(selected_rect(&controller), Rect::new(150, 175, 100, 50));
+
+        drag(&mut controller, (700, 200), (700, 200));
+        assert_eq!(selected_rect(&controller), Rect::new(633, 167, 134, 67));
+    }
+
+    #[test]
+    fn test_keyboard_selection() {
+        let mut controller = controller();
+        controller.handle_event(InputEvent::Motion {
+            position: (300, 200),
+        });
+
+        controller.handle_event(InputEvent::Key(Key::Text(" ".to_string())));
+        controller.handle_event(InputEvent::Motion {
+            position: (350, 260),
+        });
+        controller.handle_event(InputEvent::Key(Key::Text(" ".to_string())));
+        assert_eq!(selected_rect(&controller), Rect::new(300, 200, 50, 60));
+
+        // Moving the pointer doesn't change it anymore
+        controller.handle_event(InputEvent::Motion {
+            position: (400, 400),
+        });
+        assert_eq!(selected_rect(&controller), Rect::new(300, 200, 50, 60));
+    }
+}
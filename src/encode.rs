@@ -0,0 +1,567 @@
+//! Encoding the screenshots into the formats they can be saved and copied in.
+
+use std::{
+    collections::HashMap,
+    io::{Cursor, Write},
+    path::Path,
+};
+
+use chrono::{DateTime, Local};
+use image::{
+    codecs::{
+        pnm::{PnmSubtype, SampleEncoding},
+        webp::{WebPEncoder, WebPQuality},
+    },
+    error::{EncodingError, ImageFormatHint},
+    ColorType, DynamicImage, ImageError, ImageFormat, ImageOutputFormat, ImageResult, RgbaImage,
+};
+use serde::Deserialize;
+
+use crate::{types::Config, Capture, Rect, SelectionMode};
+
+/// The quality of JPEG images without one given, high enough to keep text legible
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Webp,
+    Ppm,
+    Qoi,
+}
+
+impl Format {
+    /// The format the extension of the path stands for
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .ok_or_else(|| {
+                format!(
+                    "\"{}\" has no extension to tell the format from, pass it with --format",
+                    path.display()
+                )
+            })?
+            .to_string_lossy()
+            .to_lowercase();
+
+        match extension.as_str() {
+            "png" => Ok(Self::Png),
+            "jpg" | "jpeg" => Ok(Self::Jpeg),
+            "webp" => Ok(Self::Webp),
+            "ppm" => Ok(Self::Ppm),
+            "qoi" => Ok(Self::Qoi),
+            _ => Err(format!(
+                "Unsupported extension \"{}\", the supported ones are png, jpg, jpeg, webp, ppm and qoi",
+                extension
+            )),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Webp => "webp",
+            Self::Ppm => "ppm",
+            Self::Qoi => "qoi",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Webp => "image/webp",
+            Self::Ppm => "image/x-portable-pixmap",
+            Self::Qoi => "image/qoi",
+        }
+    }
+
+    /// The file name with the extension of the format, replacing the extension of another
+    /// format if it has one
+    pub fn file_name(self, name: &str) -> String {
+        if Self::from_path(Path::new(name)).is_ok() {
+            Path::new(name)
+                .with_extension(self.extension())
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            format!("{}.{}", name, self.extension())
+        }
+    }
+}
+
+/// How hard PNG images are compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum PngCompression {
+    Fast,
+    Default,
+    Best,
+}
+
+/// The filter applied to the rows of PNG images before compressing them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Picks the filter that suits each row best
+    Adaptive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+    /// Store images with few colors as a palette and opaque ones without alpha
+    pub optimize: bool,
+}
+
+impl From<&Config> for PngOptions {
+    fn from(config: &Config) -> Self {
+        Self {
+            compression: config.png_compression,
+            filter: config.png_filter,
+            optimize: config.png_optimize,
+        }
+    }
+}
+
+/// Where a screenshot came from, written into PNG and JPEG images
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub time: DateTime<Local>,
+    pub mode: SelectionMode,
+    pub outputs: Vec<String>,
+    /// The region in the global compositor space
+    pub rect: Rect<i32>,
+    /// The class and the title of the window in window mode
+    pub window: Option<(String, String)>,
+}
+
+impl From<&Capture> for Metadata {
+    fn from(capture: &Capture) -> Self {
+        Self {
+            time: capture.time,
+            mode: capture.mode,
+            outputs: capture.outputs.clone(),
+            rect: capture.rect,
+            window: capture
+                .window
+                .as_ref()
+                .map(|window| (window.class.clone(), window.title.clone())),
+        }
+    }
+}
+
+impl Metadata {
+    /// The keywords and the text of the fields, in the order they are written
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("Creation Time", self.time.to_rfc2822()),
+            (
+                "Software",
+                format!("watershot {}", env!("CARGO_PKG_VERSION")),
+            ),
+            ("Mode", self.mode.to_string()),
+            ("Output", self.outputs.join(", ")),
+            (
+                "Region",
+                format!(
+                    "{}x{} at {},{}",
+                    self.rect.width, self.rect.height, self.rect.x, self.rect.y
+                ),
+            ),
+        ];
+        if let Some((class, title)) = &self.window {
+            fields.push(("Window Class", class.clone()));
+            fields.push(("Window Title", title.clone()));
+        }
+
+        fields
+    }
+
+    /// An APP1 segment with the metadata as EXIF. The time and the software have their own
+    /// tags, the rest goes into the image description, with the characters that are not
+    /// ASCII escaped like `\u{e4}` as the tag is ASCII only.
+    fn exif(&self) -> Vec<u8> {
+        let description = self
+            .fields()
+            .into_iter()
+            .skip(2)
+            .map(|(keyword, text)| format!("{}: {}", keyword, text))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .chars()
+            .map(|c| {
+                if c.is_ascii() {
+                    c.to_string()
+                } else {
+                    c.escape_unicode().to_string()
+                }
+            })
+            .collect::<String>();
+        let entries = [
+            // ImageDescription, Software and DateTime, sorted by the tag as required
+            (0x010e_u16, description),
+            (0x0131, format!("watershot {}", env!("CARGO_PKG_VERSION"))),
+            (0x0132, self.time.format("%Y:%m:%d %H:%M:%S").to_string()),
+        ];
+
+        // Little endian TIFF header, followed by the only IFD and then the strings it points to
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8_u32.to_le_bytes());
+        tiff.extend((entries.len() as u16).to_le_bytes());
+
+        let mut offset = 8 + 2 + entries.len() * 12 + 4;
+        let mut strings = Vec::new();
+        for (tag, text) in &entries {
+            let mut text = text.as_bytes().to_vec();
+            text.push(0);
+
+            tiff.extend(tag.to_le_bytes());
+            // ASCII
+            tiff.extend(2_u16.to_le_bytes());
+            tiff.extend((text.len() as u32).to_le_bytes());
+            if text.len() <= 4 {
+                text.resize(4, 0);
+                tiff.extend(&text);
+            } else {
+                tiff.extend((offset as u32).to_le_bytes());
+                offset += text.len();
+                strings.extend(text);
+            }
+        }
+        // No next IFD
+        tiff.extend(0_u32.to_le_bytes());
+        tiff.extend(strings);
+
+        let mut segment = vec![0xff, 0xe1];
+        segment.extend(((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        segment.extend(b"Exif\0\0");
+        segment.extend(tiff);
+        segment
+    }
+}
+
+/// Encode the image in the format. The quality from 1 to 100 is used by JPEG, and makes
+/// WebP lossy instead of lossless.
+pub fn encode(
+    image: &DynamicImage,
+    format: Format,
+    quality: Option<u8>,
+    png: &PngOptions,
+    metadata: Option<&Metadata>,
+) -> ImageResult<Vec<u8>> {
+    let mut buf = Cursor::new(Vec::new());
+
+    match format {
+        Format::Png => encode_png(image, png, metadata, &mut buf).map_err(|why| {
+            ImageError::Encoding(EncodingError::new(
+                ImageFormatHint::Exact(ImageFormat::Png),
+                why,
+            ))
+        })?,
+        // Neither has an alpha channel
+        Format::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()).write_to(
+            &mut buf,
+            ImageOutputFormat::Jpeg(quality.unwrap_or(DEFAULT_JPEG_QUALITY)),
+        )?,
+        Format::Ppm => DynamicImage::ImageRgb8(image.to_rgb8()).write_to(
+            &mut buf,
+            ImageOutputFormat::Pnm(PnmSubtype::Pixmap(SampleEncoding::Binary)),
+        )?,
+        Format::Webp => {
+            let quality = quality.map_or(WebPQuality::lossless(), WebPQuality::lossy);
+            let image = image.to_rgba8();
+            WebPEncoder::new_with_quality(&mut buf, quality).encode(
+                &image,
+                image.width(),
+                image.height(),
+                ColorType::Rgba8,
+            )?;
+        }
+        Format::Qoi => image.write_to(&mut buf, ImageOutputFormat::Qoi)?,
+    }
+
+    let mut buf = buf.into_inner();
+    if let (Format::Jpeg, Some(metadata)) = (format, metadata) {
+        let exif = metadata.exif();
+        // The length of a segment has to fit in 16 bits
+        if exif.len() <= u16::MAX as usize {
+            // Right after the start of image marker
+            buf.splice(2..2, exif);
+        } else {
+            log::warn!("The metadata is too long to fit into the image, leaving it out");
+        }
+    }
+
+    Ok(buf)
+}
+
+fn encode_png(
+    image: &DynamicImage,
+    options: &PngOptions,
+    metadata: Option<&Metadata>,
+    w: impl Write,
+) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(w, image.width(), image.height());
+
+    for (keyword, text) in metadata.map(Metadata::fields).unwrap_or_default() {
+        // tEXt chunks are Latin-1, which window titles often don't fit in
+        if text.is_ascii() {
+            encoder.add_text_chunk(keyword.to_string(), text)?;
+        } else {
+            encoder.add_itxt_chunk(keyword.to_string(), text)?;
+        }
+    }
+
+    encoder.set_compression(match options.compression {
+        PngCompression::Fast => png::Compression::Fast,
+        PngCompression::Default => png::Compression::Default,
+        PngCompression::Best => png::Compression::Best,
+    });
+    let (filter, adaptive) = match options.filter {
+        PngFilter::None => (
+            png::FilterType::NoFilter,
+            png::AdaptiveFilterType::NonAdaptive,
+        ),
+        PngFilter::Sub => (png::FilterType::Sub, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Up => (png::FilterType::Up, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Avg => (png::FilterType::Avg, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Paeth => (png::FilterType::Paeth, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Adaptive => (png::FilterType::Sub, png::AdaptiveFilterType::Adaptive),
+    };
+    encoder.set_filter(filter);
+    encoder.set_adaptive_filter(adaptive);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data = if options.optimize {
+        let image = image.to_rgba8();
+        let opaque = image.pixels().all(|pixel| pixel[3] == u8::MAX);
+
+        if let Some((palette, indices)) = palette(&image) {
+            let depth = match palette.len() {
+                0..=2 => png::BitDepth::One,
+                3..=4 => png::BitDepth::Two,
+                5..=16 => png::BitDepth::Four,
+                _ => png::BitDepth::Eight,
+            };
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_depth(depth);
+            encoder.set_palette(
+                palette
+                    .iter()
+                    .flat_map(|color| &color[..3])
+                    .copied()
+                    .collect::<Vec<_>>(),
+            );
+            if !opaque {
+                encoder.set_trns(palette.iter().map(|color| color[3]).collect::<Vec<_>>());
+            }
+            pack(&indices, image.width() as usize, depth as usize)
+        } else if opaque {
+            encoder.set_color(png::ColorType::Rgb);
+            DynamicImage::ImageRgba8(image).to_rgb8().into_raw()
+        } else {
+            encoder.set_color(png::ColorType::Rgba);
+            image.into_raw()
+        }
+    } else {
+        match image {
+            DynamicImage::ImageRgb8(image) => {
+                encoder.set_color(png::ColorType::Rgb);
+                image.as_raw().clone()
+            }
+            _ => {
+                encoder.set_color(png::ColorType::Rgba);
+                image.to_rgba8().into_raw()
+            }
+        }
+    };
+
+    encoder.write_header()?.write_image_data(&data)
+}
+
+/// The colors of the image and the index of the color of each pixel, if it has no more
+/// colors than fit in a palette
+fn palette(image: &RgbaImage) -> Option<(Vec<[u8; 4]>, Vec<u8>)> {
+    let mut palette = Vec::new();
+    let mut lookup = HashMap::new();
+    let mut indices = Vec::with_capacity(image.len() / 4);
+
+    for pixel in image.pixels() {
+        let index = match lookup.get(&pixel.0) {
+            Some(index) => *index,
+            None if palette.len() < 256 => {
+                let index = palette.len() as u8;
+                palette.push(pixel.0);
+                lookup.insert(pixel.0, index);
+                index
+            }
+            None => return None,
+        };
+        indices.push(index);
+    }
+
+    Some((palette, indices))
+}
+
+/// Pack the indices into rows of the bit depth, each row starting at a new byte
+fn pack(indices: &[u8], width: usize, depth: usize) -> Vec<u8> {
+    if depth == 8 {
+        return indices.to_vec();
+    }
+
+    let per_byte = 8 / depth;
+    indices
+        .chunks(width)
+        .flat_map(|row| {
+            row.chunks(per_byte).map(|pixels| {
+                pixels
+                    .iter()
+                    .enumerate()
+                    .fold(0, |byte, (i, index)| byte | index << (8 - depth * (i + 1)))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use image::{ImageFormat, Rgba, RgbaImage};
+
+    use super::*;
+
+    const PNG: PngOptions = PngOptions {
+        compression: PngCompression::Fast,
+        filter: PngFilter::Adaptive,
+        optimize: false,
+    };
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path(Path::new("a/b.PNG")), Ok(Format::Png));
+        assert_eq!(Format::from_path(Path::new("shot.jpg")), Ok(Format::Jpeg));
+        assert!(Format::from_path(Path::new("shot.gif")).is_err());
+        assert!(Format::from_path(Path::new("shot")).is_err());
+
+        assert_eq!(Format::Jpeg.file_name("shot.png"), "shot.jpg");
+        assert_eq!(Format::Webp.file_name("shot_12.30"), "shot_12.30.webp");
+    }
+
+    #[test]
+    fn test_encode() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 4, Rgba([10, 200, 30, 255])));
+
+        for (format, image_format) in [
+            (Format::Png, ImageFormat::Png),
+            (Format::Jpeg, ImageFormat::Jpeg),
+            (Format::Webp, ImageFormat::WebP),
+            (Format::Ppm, ImageFormat::Pnm),
+            (Format::Qoi, ImageFormat::Qoi),
+        ] {
+            let encoded = encode(&image, format, Some(80), &PNG, None).unwrap();
+            assert_eq!(
+                image::guess_format(&encoded).unwrap(),
+                image_format,
+                "Encoding {format:?}"
+            );
+        }
+
+        // Lossless without a quality
+        let encoded = encode(&image, Format::Webp, None, &PNG, None).unwrap();
+        assert_eq!(&encoded[12..16], b"VP8L");
+        let encoded = encode(&image, Format::Webp, Some(80), &PNG, None).unwrap();
+        assert_eq!(&encoded[12..16], b"VP8 ");
+    }
+
+    fn metadata() -> Metadata {
+        Metadata {
+            time: Local.with_ymd_and_hms(2024, 3, 5, 14, 7, 9).unwrap(),
+            mode: SelectionMode::Window,
+            outputs: vec!["DP-1".to_string()],
+            rect: Rect::new(-100, 20, 300, 200),
+            window: Some(("Alacritty".to_string(), "Grüße".to_string())),
+        }
+    }
+
+    #[test]
+    fn test_png_metadata() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+        let encoded = encode(&image, Format::Png, None, &PNG, Some(&metadata())).unwrap();
+
+        let reader = png::Decoder::new(encoded.as_slice()).read_info().unwrap();
+        let info = reader.info();
+        let text = info
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.as_str(), chunk.text.as_str()))
+            .collect::<Vec<_>>();
+        assert!(text.contains(&("Mode", "window")));
+        assert!(text.contains(&("Region", "300x200 at -100,20")));
+        assert!(text.contains(&("Window Class", "Alacritty")));
+        assert_eq!(info.utf8_text[0].keyword, "Window Title");
+        assert_eq!(info.utf8_text[0].get_text().unwrap(), "Grüße");
+    }
+
+    #[test]
+    fn test_jpeg_exif() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+        let encoded = encode(&image, Format::Jpeg, None, &PNG, Some(&metadata())).unwrap();
+
+        assert_eq!(&encoded[..4], [0xff, 0xd8, 0xff, 0xe1]);
+        let length = u16::from_be_bytes([encoded[4], encoded[5]]) as usize;
+        let exif = &encoded[6..4 + length];
+        assert!(exif.starts_with(b"Exif\0\0II*\0"));
+        let contains = |text: &str| {
+            exif.windows(text.len())
+                .any(|window| window == text.as_bytes())
+        };
+        assert!(contains("2024:03:05 14:07:09\0"));
+        assert!(contains("Window Title: Gr\\u{fc}\\u{df}e\0"));
+
+        // Still a valid image after the segment
+        assert_eq!(image::load_from_memory(&encoded).unwrap().width(), 4);
+    }
+
+    /// The color type and the bit depth from the header
+    fn png_header(encoded: &[u8]) -> (u8, u8) {
+        (encoded[25], encoded[24])
+    }
+
+    #[test]
+    fn test_png_optimization() {
+        let optimized = PngOptions {
+            optimize: true,
+            ..PNG
+        };
+
+        // Three colors fit in two bits, one of them translucent
+        let mut image = RgbaImage::from_pixel(7, 3, Rgba([255, 255, 255, 255]));
+        image.put_pixel(0, 0, Rgba([0, 0, 0, 255]));
+        image.put_pixel(6, 2, Rgba([255, 0, 0, 128]));
+        let image = DynamicImage::ImageRgba8(image);
+
+        let encoded = encode(&image, Format::Png, None, &optimized, None).unwrap();
+        assert_eq!(png_header(&encoded), (3, 2));
+        let decoded = image::load_from_memory(&encoded).unwrap().to_rgba8();
+        assert_eq!(decoded, image.to_rgba8());
+
+        // Too many colors for a palette, but opaque
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(32, 32, |x, y| {
+            Rgba([x as u8 * 8, y as u8 * 8, 0, 255])
+        }));
+        let encoded = encode(&image, Format::Png, None, &optimized, None).unwrap();
+        assert_eq!(png_header(&encoded), (2, 8));
+        let decoded = image::load_from_memory(&encoded).unwrap().to_rgba8();
+        assert_eq!(decoded, image.to_rgba8());
+
+        let encoded = encode(&image, Format::Png, None, &PNG, None).unwrap();
+        assert_eq!(png_header(&encoded), (6, 8));
+    }
+}
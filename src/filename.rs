@@ -0,0 +1,212 @@
+//! Generating the names of saved screenshots from templates.
+//!
+//! A template is a [strftime](https://docs.rs/chrono/latest/chrono/format/strftime/index.html)
+//! format string of the capture time, that can also contain these placeholders:
+//!
+//! - `{mode}`: `region`, `display` or `window`
+//! - `{class}` and `{title}`: of the selected window in window mode, empty otherwise
+//! - `{output}`: the names of the outputs the region is on, joined with `+`
+//! - `{width}` and `{height}`: the size of the image in pixels
+//! - `{x}` and `{y}`: the position of the region in the global compositor space
+//! - `{counter}`: the lowest number from 1 up that doesn't name an existing file
+
+use std::{
+    fmt::Write,
+    fs::{File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::Capture;
+
+pub const DEFAULT_TEMPLATE: &str = "Watershot_%d-%m-%Y_%H:%M.png";
+
+/// Fill in the template for the capture, `{counter}` is left for [`create_available`]
+pub fn expand(template: &str, capture: &Capture) -> Result<String, String> {
+    let mut name = String::new();
+    let mut rest = template;
+
+    // A single pass, so the values are never read as strftime fields or placeholders
+    loop {
+        let literal_end = rest.find('{').unwrap_or(rest.len());
+        write!(name, "{}", capture.time.format(&rest[..literal_end])).map_err(|_| {
+            format!(
+                "Invalid strftime field in the filename template \"{}\"",
+                template
+            )
+        })?;
+        rest = &rest[literal_end..];
+        if rest.is_empty() {
+            return Ok(name);
+        }
+
+        let value = rest
+            .find('}')
+            .and_then(|end| Some((end, placeholder(&rest[1..end], capture)?)));
+        match value {
+            Some((end, value)) => {
+                name.push_str(&sanitize(&value));
+                rest = &rest[end + 1..];
+            }
+            // Not a placeholder, or `{counter}`
+            None => {
+                name.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+}
+
+/// The value of the placeholder with the name between the braces
+fn placeholder(name: &str, capture: &Capture) -> Option<String> {
+    let window = capture.window.as_ref();
+
+    Some(match name {
+        "mode" => capture.mode.to_string(),
+        "class" => window.map_or_else(String::new, |window| window.class.clone()),
+        "title" => window.map_or_else(String::new, |window| window.title.clone()),
+        "output" => capture.outputs.join("+"),
+        "width" => capture.image.width().to_string(),
+        "height" => capture.image.height().to_string(),
+        "x" => capture.rect.x.to_string(),
+        "y" => capture.rect.y.to_string(),
+        _ => return None,
+    })
+}
+
+/// Create a new file in the directory for the file name without overwriting anything. Without
+/// a `{counter}` in the name, one is added before the extension if the name is taken.
+pub fn create_available(directory: &Path, name: &str) -> io::Result<(PathBuf, File)> {
+    let candidate = |counter: usize| {
+        if name.contains("{counter}") {
+            name.replace("{counter}", &counter.to_string())
+        } else if counter == 1 {
+            name.to_string()
+        } else {
+            let path = Path::new(name);
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            match path.extension() {
+                Some(extension) => {
+                    format!("{}_{}.{}", stem, counter, extension.to_string_lossy())
+                }
+                None => format!("{}_{}", stem, counter),
+            }
+        }
+    };
+
+    let mut counter = 1;
+    loop {
+        let path = directory.join(candidate(counter));
+        // Taken atomically, so screenshots saved at the same time can't overwrite each other
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(why) if why.kind() == io::ErrorKind::AlreadyExists => counter += 1,
+            Err(why) => return Err(why),
+        }
+    }
+}
+
+/// The `file://` URI of the absolute path, as offered to paste the saved screenshot as a file
+pub fn file_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => write!(uri, "%{:02X}", byte).unwrap(),
+        }
+    }
+
+    uri
+}
+
+/// Keep values like window titles from being read as directories
+fn sanitize(value: &str) -> String {
+    value.replace(['/', '\0'], "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::{Local, TimeZone};
+    use image::DynamicImage;
+
+    use super::*;
+    use crate::{window::WindowDescriptor, Config, Rect, SelectionMode};
+
+    fn capture() -> Capture {
+        Capture {
+            rect: Rect::new(-100, 20, 300, 200),
+            image: DynamicImage::new_rgba8(600, 400),
+            time: Local.with_ymd_and_hms(2024, 3, 5, 14, 7, 9).unwrap(),
+            mode: SelectionMode::Window,
+            window: Some(WindowDescriptor {
+                initial_title: String::new(),
+                title: "~/src/watershot".to_string(),
+                initial_class: String::new(),
+                class: "Alacritty".to_string(),
+                rect: Some(Rect::new(-100, 20, 300, 200)),
+            }),
+            outputs: vec!["DP-1".to_string(), "HDMI-A-1".to_string()],
+            config: Config::default(),
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            expand(DEFAULT_TEMPLATE, &capture()),
+            Ok("Watershot_05-03-2024_14:07.png".to_string())
+        );
+        assert_eq!(
+            expand(
+                "%Y%m%d-%H%M%S_{mode}_{class}_{title}_{output}_{width}x{height}+{x}+{y}_{counter}.png",
+                &capture()
+            ),
+            Ok(
+                "20240305-140709_window_Alacritty_~_src_watershot_DP-1+HDMI-A-1_600x400+-100+20_{counter}.png"
+                    .to_string()
+            )
+        );
+        assert!(expand("%Q.png", &capture()).is_err());
+    }
+
+    #[test]
+    fn test_expand_values_literally() {
+        let mut capture = capture();
+        capture.window.as_mut().unwrap().title = "%Y {output} {counter".to_string();
+
+        assert_eq!(
+            expand("{title}_{counter}_{unknown}", &capture),
+            Ok("%Y {output} {counter_{counter}_{unknown}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_create_available() {
+        let directory = std::env::temp_dir().join(format!("watershot-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let create = |name| create_available(&directory, name).unwrap().0;
+
+        assert_eq!(create("shot.png"), directory.join("shot.png"));
+        assert_eq!(create("shot.png"), directory.join("shot_2.png"));
+        assert_eq!(create("shot.png"), directory.join("shot_3.png"));
+
+        fs::write(directory.join("shot-1.png"), []).unwrap();
+        assert_eq!(create("shot-{counter}.png"), directory.join("shot-2.png"));
+
+        assert!(create_available(&directory.join("missing"), "shot.png").is_err());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_file_uri() {
+        assert_eq!(
+            file_uri(Path::new("/home/user/Pictures/Watershot 1:2 ä.png")),
+            "file:///home/user/Pictures/Watershot%201%3A2%20%C3%A4.png"
+        );
+    }
+}
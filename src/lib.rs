@@ -0,0 +1,328 @@
+//! Interactive region selection and capture for wlroots based compositors.
+//!
+//! [`select_region`] captures the outputs, shows the selection overlay on top
+//! of them and returns the selected part of the screenshot.
+//!
+//! ```no_run
+//! let capture = watershot::select_region(watershot::Options::default())?;
+//! println!("Selected {:?}", capture.rect);
+//! # Ok::<(), watershot::Error>(())
+//! ```
+
+use std::fmt;
+
+use capture::{CaptureContext, CapturedOutput};
+use chrono::{DateTime, Local};
+use image::DynamicImage;
+use runtime_data::RuntimeData;
+use smithay_client_toolkit::reexports::client::{globals::registry_queue_init, Connection};
+use traits::{Contains, ToLocal};
+use types::{ExitState, Monitor, Selection};
+
+mod annotation;
+mod capture;
+mod controller;
+pub mod encode;
+pub mod filename;
+mod macros;
+mod protocols;
+mod runtime_data;
+mod traits;
+mod types;
+
+pub mod window;
+
+mod sctk_impls {
+    mod compositor_handler;
+    mod keyboard_handler;
+    mod layer_shell_handler;
+    mod output_handler;
+    mod pointer_handler;
+    mod provides_registry_state;
+    mod seat_handler;
+    mod shm_handler;
+}
+mod rendering;
+
+pub use capture::CaptureError;
+pub use types::{Config, Options, Rect};
+use window::WindowDescriptor;
+
+/// The result of a successful selection
+pub struct Capture {
+    /// The selected region in the global compositor space, or just the size of the image
+    /// for a window picked from the list, as the compositor doesn't tell where it is
+    pub rect: Rect<i32>,
+    /// The contents of the region at the full resolution of the outputs
+    pub image: DynamicImage,
+    /// When the screen was captured
+    pub time: DateTime<Local>,
+    pub mode: SelectionMode,
+    /// The selected window in window mode
+    pub window: Option<WindowDescriptor>,
+    /// The names of the outputs the region is on
+    pub outputs: Vec<String>,
+    /// The config the region was selected with, loaded once for the whole capture
+    pub config: Config,
+}
+
+/// How the region was selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionMode {
+    Region,
+    Display,
+    Window,
+}
+
+impl fmt::Display for SelectionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Region => write!(f, "region"),
+            Self::Display => write!(f, "display"),
+            Self::Window => write!(f, "window"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The selection was cancelled by the user
+    Cancelled,
+    /// Not running within a Wayland session
+    NoWaylandSession,
+    /// The compositor supports none of the ways to capture the screen
+    NoCaptureBackend,
+    Capture(CaptureError),
+    /// The window picked from the list can't be captured by itself, and the compositor
+    /// doesn't tell where it is to crop it out of the screen instead
+    WindowCapture(CaptureError),
+    /// The connection to the compositor failed
+    Wayland(String),
+    /// The compositor lacks a protocol the overlay needs
+    MissingProtocol(&'static str),
+    /// The font for the overlay text couldn't be found or loaded
+    Font(String),
+    /// No GPU to render the overlay with could be set up
+    Gpu(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cancelled => write!(f, "Selection cancelled"),
+            Self::NoWaylandSession => write!(
+                f,
+                "Could not connect to the Wayland server, make sure you run watershot within a Wayland session"
+            ),
+            Self::NoCaptureBackend => write!(
+                f,
+                "No way to capture the screen is available, if your compositor does not support wlr-screencopy, try running with --grim"
+            ),
+            Self::Capture(why) => write!(f, "Failed to capture the outputs: {}", why),
+            Self::WindowCapture(why) => write!(f, "Failed to capture the window: {}", why),
+            Self::Wayland(why) => write!(f, "Wayland error: {}", why),
+            Self::MissingProtocol(protocol) => {
+                write!(f, "The compositor does not support {}", protocol)
+            }
+            Self::Font(why) => write!(f, "Failed to load the font: {}", why),
+            Self::Gpu(why) => write!(f, "Failed to set up rendering: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<CaptureError> for Error {
+    fn from(value: CaptureError) -> Self {
+        Self::Capture(value)
+    }
+}
+
+/// Capture the screen and let the user select a region of it
+pub fn select_region(options: Options) -> Result<Capture, Error> {
+    let conn = Connection::connect_to_env().map_err(|_| Error::NoWaylandSession)?;
+
+    let (globals, mut event_queue) =
+        registry_queue_init(&conn).map_err(|why| Error::Wayland(why.to_string()))?;
+    let qh = event_queue.handle();
+    // Checks for layer-shell, before a portal would take and save a screenshot for nothing
+    let mut runtime_data = RuntimeData::new(&conn, &qh, &globals, options)?;
+
+    // Fetch the outputs from the compositor
+    event_queue
+        .roundtrip(&mut runtime_data)
+        .map_err(|why| Error::Wayland(why.to_string()))?;
+    let outputs = runtime_data
+        .output_state
+        .outputs()
+        .map(|output| {
+            let info = runtime_data.output_state.info(&output).unwrap();
+            (output, info)
+        })
+        .collect::<Vec<_>>();
+
+    let context = CaptureContext {
+        conn: &conn,
+        globals: &globals,
+        options: &runtime_data.options,
+    };
+    let mut capture_backend =
+        capture::get_preferred_backend(&context).ok_or(Error::NoCaptureBackend)?;
+
+    // Windows picked from the list can only be captured by themselves
+    if runtime_data.controller.window_list_mode() && !capture_backend.captures_windows() {
+        log::info!("The capture backend can't capture single windows, window mode is unavailable");
+        runtime_data.controller.disable_window_mode();
+    }
+
+    // Capture before any of our own surfaces are mapped so they don't end up in the screenshot
+    let captured = capture_backend.capture_outputs(&outputs)?;
+    let time = Local::now();
+
+    // Has to be iterated first to get the full area size
+    for output in &captured {
+        runtime_data.controller.area.extend(&output.rect);
+    }
+
+    if runtime_data.controller.auto_capture {
+        runtime_data.controller.capture_initial_selection();
+    }
+
+    runtime_data.image = capture::composite(&runtime_data.controller.area, &captured);
+    runtime_data.scale_factor =
+        runtime_data.image.width() as f32 / runtime_data.controller.area.width as f32;
+
+    for ((output, info), CapturedOutput { rect, image }) in outputs.into_iter().zip(captured) {
+        runtime_data
+            .controller
+            .scales
+            .push((rect, image.width() as f32 / rect.width as f32));
+        runtime_data.monitors.push(Monitor::new(
+            rect,
+            &qh,
+            &conn,
+            output,
+            info,
+            image,
+            &runtime_data,
+        ));
+    }
+
+    event_queue
+        .roundtrip(&mut runtime_data)
+        .map_err(|why| Error::Wayland(why.to_string()))?;
+
+    loop {
+        event_queue
+            .blocking_dispatch(&mut runtime_data)
+            .map_err(|why| Error::Wayland(why.to_string()))?;
+        match runtime_data.controller.exit {
+            ExitState::ExitOnly => return Err(Error::Cancelled),
+            ExitState::ExitWithSelection(rect) => {
+                let area = runtime_data.controller.area;
+                let global_rect =
+                    Rect::new(rect.x + area.x, rect.y + area.y, rect.width, rect.height);
+
+                // Capture the window itself if possible so overlapping windows don't end up in it
+                let window_image = match &runtime_data.controller.selection {
+                    Selection::Window(Some(window)) => {
+                        match capture_backend.capture_window(window) {
+                            Ok(image) => Some(image),
+                            Err(CaptureError::Unsupported) => None,
+                            Err(why) => {
+                                log::warn!(
+                                    "Failed to capture the window, cropping it out of the screenshot instead: {}",
+                                    why
+                                );
+                                None
+                            }
+                        }
+                    }
+                    _ => None,
+                };
+
+                let mut image = window_image.unwrap_or_else(|| {
+                    match runtime_data
+                        .monitors
+                        .iter()
+                        .find(|mon| mon.rect.to_local(&area).contains(&rect))
+                    {
+                        Some(mon) => capture::crop(
+                            &mon.image,
+                            &rect.to_local(&mon.rect.to_local(&area)),
+                            mon.image.width() as f32 / mon.rect.width as f32,
+                        ),
+                        None => {
+                            capture::crop(&runtime_data.image, &rect, runtime_data.scale_factor)
+                        }
+                    }
+                });
+
+                // The fixed size is rounded up to logical pixels on fractional scales
+                if let (Some(size), Selection::Rectangle(_)) = (
+                    runtime_data.controller.fixed_size,
+                    &runtime_data.controller.selection,
+                ) {
+                    image = image.crop_imm(
+                        0,
+                        0,
+                        image.width().min(size.width as u32),
+                        image.height().min(size.height as u32),
+                    );
+                }
+
+                annotation::rasterize(
+                    &mut image,
+                    &runtime_data.controller.annotations,
+                    &global_rect,
+                    &runtime_data.font,
+                );
+
+                let (mode, window) = match &runtime_data.controller.selection {
+                    Selection::Rectangle(_) => (SelectionMode::Region, None),
+                    Selection::Display(_) => (SelectionMode::Display, None),
+                    Selection::Window(window) => (SelectionMode::Window, window.clone()),
+                };
+                let outputs = runtime_data
+                    .monitors
+                    .iter()
+                    .filter(|mon| mon.rect.intersects(&global_rect))
+                    .filter_map(|mon| mon.output_info.name.clone())
+                    .collect();
+
+                return Ok(Capture {
+                    rect: global_rect,
+                    image,
+                    time,
+                    mode,
+                    window,
+                    outputs,
+                    config: std::mem::take(&mut runtime_data.config),
+                });
+            }
+            ExitState::ExitWithWindow => {
+                let Selection::Window(Some(window)) = &runtime_data.controller.selection else {
+                    return Err(Error::WindowCapture(CaptureError::Failed(
+                        "No window is selected".to_string(),
+                    )));
+                };
+
+                let image = capture_backend
+                    .capture_window(window)
+                    .map_err(Error::WindowCapture)?;
+
+                return Ok(Capture {
+                    // Where the window is isn't known
+                    rect: Rect::new(0, 0, image.width() as i32, image.height() as i32),
+                    image,
+                    time,
+                    mode: SelectionMode::Window,
+                    window: Some(window.clone()),
+                    outputs: Vec::new(),
+                    config: std::mem::take(&mut runtime_data.config),
+                });
+            }
+            ExitState::None => (),
+        }
+    }
+}
@@ -1,180 +1,214 @@
-use std::io::{self, Cursor, Write};
-
-use chrono::Local;
-use clap::Parser;
-use image::{DynamicImage, ImageFormat};
-use log::{error, info};
-use runtime_data::RuntimeData;
-use smithay_client_toolkit::reexports::client::{globals::registry_queue_init, Connection};
-use traits::{Contains, ToLocal};
-use types::{Args, Config, ExitState, Monitor, Rect, SaveLocation, Selection};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand};
+use log::{error, info, warn};
+use watershot::{
+    encode::{self, Format, Metadata, PngOptions},
+    filename, Error, Options,
+};
 use wl_clipboard_rs::copy;
 
-mod macros;
-mod runtime_data;
-mod traits;
-mod types;
-
-pub mod window;
-
-mod sctk_impls {
-    mod compositor_handler;
-    mod keyboard_handler;
-    mod layer_shell_handler;
-    mod output_handler;
-    mod pointer_handler;
-    mod provides_registry_state;
-    mod seat_handler;
-    mod shm_handler;
+#[derive(Parser, Clone, Debug)]
+#[command(author, version, about)]
+pub struct Args {
+    /// Copy the screenshot after exit
+    #[arg(short, long)]
+    pub copy: bool,
+
+    /// Output the screenshot into stdout
+    #[arg(short, long)]
+    pub stdout: bool,
+
+    /// The format of the image. Defaults to the extension of the path when saving
+    /// to one, otherwise to PNG
+    #[arg(short, long, value_enum)]
+    pub format: Option<Format>,
+
+    /// The quality of JPEG and WebP images from 1 to 100, WebP images are lossless without it
+    #[arg(short, long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub quality: Option<u8>,
+
+    /// Stop offering the copied screenshot after this many seconds
+    #[arg(long, value_name = "SECONDS")]
+    pub copy_timeout: Option<u64>,
+
+    /// Stop offering the copied screenshot after it has been read once
+    ///
+    /// Only the chosen format is offered then, as a paste that first reads
+    /// another type would use up the single read
+    #[arg(long)]
+    pub serve_once: bool,
+
+    /// Save the image into a file
+    #[command(subcommand)]
+    pub save: Option<SaveLocation>,
+
+    #[command(flatten)]
+    pub options: Options,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum SaveLocation {
+    /// The path to save the image to
+    Path { path: String },
+    /// The directory to save the image to with a generated name
+    Directory {
+        path: String,
+        /// The template of the name, overriding `filename_template` in the config.
+        /// Takes strftime fields and the placeholders {mode}, {class}, {title},
+        /// {output}, {width}, {height}, {x}, {y} and {counter}
+        #[arg(long)]
+        template: Option<String>,
+    },
 }
-mod rendering;
 
 fn main() {
     let args = Args::parse();
     env_logger::init();
 
-    if let Some(image) = gui(&args) {
-        // Save the file if an argument for that is present
-        if let Some(save_location) = &args.save {
-            match save_location {
-                SaveLocation::Path { path } => {
-                    if let Err(why) = image.save(path) {
-                        error!("Error saving image: {}", why);
+    // Known before selecting so an unsupported extension doesn't waste the selection
+    let format = match (args.format, &args.save) {
+        (Some(format), _) => format,
+        (None, Some(SaveLocation::Path { path })) => match Format::from_path(Path::new(path)) {
+            Ok(format) => format,
+            Err(why) => {
+                error!("{}", why);
+                std::process::exit(1);
+            }
+        },
+        (None, _) => Format::Png,
+    };
+
+    let capture = match watershot::select_region(args.options.clone()) {
+        Ok(capture) => capture,
+        Err(Error::Cancelled) => return,
+        Err(why) => {
+            error!("{}", why);
+            std::process::exit(1);
+        }
+    };
+
+    let config = &capture.config;
+    // Only saved images carry the metadata, pasting them shouldn't pass on the window titles
+    let metadata = config.metadata.then(|| Metadata::from(&capture));
+    let encode = |format, metadata| {
+        encode::encode(
+            &capture.image,
+            format,
+            args.quality,
+            &PngOptions::from(config),
+            metadata,
+        )
+    };
+    let encode_or_exit = |metadata| match encode(format, metadata) {
+        Ok(buf) => buf,
+        Err(why) => {
+            error!("Failed to encode the image: {}", why);
+            std::process::exit(1);
+        }
+    };
+
+    // Save the file if an argument for that is present
+    let saved = args.save.as_ref().and_then(|save_location| {
+        let data = encode_or_exit(metadata.as_ref());
+        let saved = match save_location {
+            SaveLocation::Path { path } => fs::write(path, &data).map(|()| PathBuf::from(path)),
+            SaveLocation::Directory { path, template } => {
+                let template = template.as_ref().unwrap_or(&config.filename_template);
+                match filename::expand(template, &capture) {
+                    Ok(name) => {
+                        filename::create_available(Path::new(path), &format.file_name(&name))
+                            .and_then(|(path, mut file)| file.write_all(&data).map(|()| path))
                     }
-                }
-                SaveLocation::Directory { path } => {
-                    let local = Local::now();
-                    if let Err(why) = image.save(
-                        local
-                            .format(&format!("{}/Watershot_%d-%m-%Y_%H:%M.png", path))
-                            .to_string(),
-                    ) {
+                    Err(why) => {
                         error!("Error saving image: {}", why);
+                        return None;
                     }
                 }
             }
+        };
+
+        match saved {
+            Ok(path) => Some(path),
+            Err(why) => {
+                error!("Error saving image: {}", why);
+                None
+            }
         }
+    });
 
-        // Save the selected image into the buffer
-        let mut buf = Cursor::new(Vec::new());
-        image
-            .write_to(&mut buf, ImageFormat::Png)
-            .expect("Failed to write image to buffer as PNG");
+    let buf = if args.stdout || args.copy {
+        encode_or_exit(None)
+    } else {
+        Vec::new()
+    };
 
-        let buf = buf.into_inner();
+    if args.stdout {
+        if let Err(why) = io::stdout().lock().write_all(&buf) {
+            error!("Failed to write image content to stdout: {}", why);
+        }
+    }
 
-        if args.stdout {
-            if let Err(why) = io::stdout().lock().write_all(&buf) {
-                error!("Failed to write image content to stdout: {}", why);
+    // Fork to serve copy requests
+    if args.copy {
+        match unsafe { nix::unistd::fork() } {
+            Ok(nix::unistd::ForkResult::Parent { .. }) => {
+                info!("Forked to serve copy requests")
             }
-        }
+            Ok(nix::unistd::ForkResult::Child) => {
+                if let Some(timeout) = args.copy_timeout {
+                    thread::spawn(move || {
+                        thread::sleep(Duration::from_secs(timeout));
+                        std::process::exit(0);
+                    });
+                }
 
-        // Fork to serve copy requests
-        if args.copy {
-            match unsafe { nix::unistd::fork() } {
-                Ok(nix::unistd::ForkResult::Parent { .. }) => {
-                    info!("Forked to serve copy requests")
+                // Offer it in the chosen format first, then in the formats most apps take
+                let mut sources = vec![mime_source(buf, format.mime_type())];
+                for other in [Format::Png, Format::Jpeg] {
+                    if other == format || args.serve_once {
+                        continue;
+                    }
+                    match encode(other, None) {
+                        Ok(buf) => sources.push(mime_source(buf, other.mime_type())),
+                        Err(why) => {
+                            warn!("Failed to encode the copied image as {:?}: {}", other, why)
+                        }
+                    }
                 }
-                Ok(nix::unistd::ForkResult::Child) => {
-                    // Serve copy requests
-                    let mut opts = copy::Options::new();
-                    opts.foreground(true);
-                    opts.copy(
-                        copy::Source::Bytes(buf.into_boxed_slice()),
-                        copy::MimeType::Autodetect,
-                    )
-                    .expect("Failed to serve copied image");
+                // File managers and some chat clients paste the saved file instead
+                if let Some(path) = saved
+                    .filter(|_| !args.serve_once)
+                    .and_then(|path| fs::canonicalize(path).ok())
+                {
+                    let uri_list = format!("{}\r\n", filename::file_uri(&path));
+                    sources.push(mime_source(uri_list.into_bytes(), "text/uri-list"));
+                }
+
+                // Serve copy requests
+                let mut opts = copy::Options::new();
+                opts.foreground(true);
+                // Exactly one read of one MIME type, the clipboard has no notion of a finished paste
+                if args.serve_once {
+                    opts.serve_requests(copy::ServeRequests::Only(1));
                 }
-                Err(why) => println!("Failed to fork: {}", why),
+                opts.copy_multi(sources)
+                    .expect("Failed to serve copied image");
             }
+            Err(why) => println!("Failed to fork: {}", why),
         }
     }
 }
 
-fn gui(args: &Args) -> Option<DynamicImage> {
-    let conn = Connection::connect_to_env();
-    if conn.is_err() {
-        log::error!("Could not connect to the Wayland server, make sure you run watershot within a Wayland session!");
-        std::process::exit(1);
-    }
-
-    let conn = conn.unwrap();
-
-    let (globals, mut event_queue) = registry_queue_init(&conn).unwrap();
-    let qh = event_queue.handle();
-    let mut runtime_data = RuntimeData::new(&qh, &globals, args.clone());
-
-    // Fetch the outputs from the compositor
-    event_queue.roundtrip(&mut runtime_data).unwrap();
-    // Has to be iterated first to get the full area size
-    let sizes = runtime_data
-        .output_state
-        .outputs()
-        .map(|output| {
-            let info = runtime_data.output_state.info(&output).unwrap();
-            let size = info
-                .logical_size
-                .map(|(w, h)| (w as u32, h as u32))
-                .expect("Can't determine monitor size!");
-            let pos = info
-                .logical_position
-                .expect("Can't determine monitor position!");
-
-            let rect = Rect {
-                x: pos.0,
-                y: pos.1,
-                width: size.0 as i32,
-                height: size.1 as i32,
-            };
-
-            // Extend the area spanning all monitors with the current monitor
-            runtime_data.area.extend(&rect);
-            (rect, output, info)
-        })
-        .collect::<Vec<_>>();
-
-    runtime_data.scale_factor = runtime_data.image.width() as f32 / runtime_data.area.width as f32;
-
-    for (rect, output, info) in sizes {
-        runtime_data
-            .monitors
-            .push(Monitor::new(rect, &qh, &conn, output, info, &runtime_data));
-    }
-
-    event_queue.roundtrip(&mut runtime_data).unwrap();
-
-    loop {
-        event_queue.blocking_dispatch(&mut runtime_data).unwrap();
-        match runtime_data.exit {
-            ExitState::ExitOnly => return None,
-            ExitState::ExitWithSelection(rect) => {
-                let image = match runtime_data.monitors.into_iter().find_map(|mon| {
-                    if mon.rect.contains(&rect) {
-                        Some(mon)
-                    } else {
-                        None
-                    }
-                }) {
-                    Some(mon) => {
-                        let rect = rect.to_local(&mon.rect);
-                        mon.image.crop_imm(
-                            rect.x as u32,
-                            rect.y as u32,
-                            rect.width as u32,
-                            rect.height as u32,
-                        )
-                    }
-                    None => runtime_data.image.crop_imm(
-                        (rect.x as f32 * runtime_data.scale_factor) as u32,
-                        (rect.y as f32 * runtime_data.scale_factor) as u32,
-                        (rect.width as f32 * runtime_data.scale_factor) as u32,
-                        (rect.height as f32 * runtime_data.scale_factor) as u32,
-                    ),
-                };
-
-                return Some(image);
-            }
-            ExitState::None => (),
-        }
+fn mime_source(data: Vec<u8>, mime_type: &str) -> copy::MimeSource {
+    copy::MimeSource {
+        source: copy::Source::Bytes(data.into_boxed_slice()),
+        mime_type: copy::MimeType::Specific(mime_type.to_string()),
     }
 }
@@ -0,0 +1,48 @@
+//! Client bindings for protocols too new to be in the `wayland-protocols` release we depend on.
+//! The protocol files are copied verbatim from wayland-protocols into `res/protocols`.
+#![allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+#![allow(non_upper_case_globals, non_snake_case, unused_imports)]
+#![allow(clippy::all)]
+
+pub mod ext_foreign_toplevel_list {
+    use wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("res/protocols/ext-foreign-toplevel-list-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("res/protocols/ext-foreign-toplevel-list-v1.xml");
+}
+
+pub mod ext_image_capture_source {
+    use super::ext_foreign_toplevel_list::*;
+    use wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use super::super::ext_foreign_toplevel_list::__interfaces::*;
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("res/protocols/ext-image-capture-source-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("res/protocols/ext-image-capture-source-v1.xml");
+}
+
+pub mod ext_image_copy_capture {
+    use super::ext_image_capture_source::*;
+    use wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use super::super::ext_image_capture_source::__interfaces::*;
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("res/protocols/ext-image-copy-capture-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("res/protocols/ext-image-copy-capture-v1.xml");
+}
@@ -1,17 +1,18 @@
-use image::RgbaImage;
+use image::{DynamicImage, GenericImageView, RgbaImage};
 use smithay_client_toolkit::output::OutputInfo;
 use wgpu::util::DeviceExt;
 use wgpu_text::glyph_brush::{HorizontalAlign, Layout, OwnedSection, OwnedText, VerticalAlign};
 
 use crate::{
+    annotation::{self, Annotation, Redaction, Shape},
+    controller::SelectionController,
     handles,
     runtime_data::RuntimeData,
     traits::{Padded, ToLocal, ToRender},
-    types::{Config, Monitor, Rect, Selection},
+    types::{Color, Config, DimensionsPosition, Monitor, Rect, Selection, Size},
+    window::WindowDescriptor,
 };
 
-use wayland_client::protocol::wl_surface;
-
 const TOP_LEFT: [f32; 2] = [-1.0, 1.0];
 const BOTTOM_LEFT: [f32; 2] = [-1.0, -1.0];
 const TOP_RIGHT: [f32; 2] = [1.0, 1.0];
@@ -29,6 +30,21 @@ const MAX_SEL_INDICES: u64 = CIRCLE_EDGES as u64 * 3 * 8 + 24;
 
 const OVERLAY_MSAA: u32 = 4;
 
+/// Marks which samples an annotation has already drawn to, so translucent ones don't blend twice
+const ANNOTATION_STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Stencil8;
+
+/// How many windows the window list shows at once
+const WINDOW_LIST_LENGTH: usize = 10;
+
+/// Vertices the annotation buffers have room for before they have to grow
+const INITIAL_ANNOTATION_VERTICES: u64 = 1024;
+
+/// The side of the magnifier loupe in logical pixels
+const LOUPE_SIZE: i32 = 160;
+/// How far the loupe is from the cursor
+const LOUPE_OFFSET: i32 = 24;
+const LOUPE_TEXT_SIZE: i32 = 14;
+
 pub struct Renderer {
     // Pipelines
     tex_pipeline: wgpu::RenderPipeline,
@@ -37,6 +53,15 @@ pub struct Renderer {
     tex_vertex_buffer: wgpu::Buffer,
 
     overlay_pipeline: wgpu::RenderPipeline,
+    /// The overlay pipeline with the annotation stencil
+    annotation_pipeline: wgpu::RenderPipeline,
+    overlay_layout: wgpu::BindGroupLayout,
+    /// Blurs or pixelates the background within the redactions
+    redaction_pipeline: wgpu::RenderPipeline,
+    redaction_layout: wgpu::BindGroupLayout,
+    /// Shows the background zoomed in around the cursor
+    loupe_pipeline: wgpu::RenderPipeline,
+    loupe_layout: wgpu::BindGroupLayout,
     shade_bind_group: wgpu::BindGroup,
     sel_bind_group: wgpu::BindGroup,
 }
@@ -45,6 +70,8 @@ pub struct Renderer {
 pub struct MonSpecificRendering {
     /// Bind group for the background texture
     bg_bind_group: wgpu::BindGroup,
+    /// The size of the background texture
+    bg_size: [u32; 2],
 
     shade_index_count: u32,
     shade_vertex_buffer: wgpu::Buffer,
@@ -54,6 +81,32 @@ pub struct MonSpecificRendering {
     sel_vertex_buffer: wgpu::Buffer,
     sel_index_buffer: wgpu::Buffer,
 
+    annotation_vertex_buffer: wgpu::Buffer,
+    annotation_index_buffer: wgpu::Buffer,
+    annotation_stencil: wgpu::TextureView,
+    /// The index range of every annotation and the color to draw it with
+    annotation_draws: Vec<(std::ops::Range<u32>, Color)>,
+    /// Bind groups for the colors of the annotations
+    annotation_bind_groups: Vec<(Color, wgpu::BindGroup)>,
+    /// The index range of every redaction and the index of the uniform describing it
+    redaction_draws: Vec<(std::ops::Range<u32>, usize)>,
+    /// A uniform buffer and its bind group per redaction, reused between frames
+    redaction_uniforms: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+    /// The part of the monitor the annotations are visible in, in physical pixels
+    annotation_scissor: Option<Rect<u32>>,
+    /// The text annotations
+    text_sections: Vec<OwnedSection>,
+    /// Draws the text annotations, separate from the other text as they are cut to the selection
+    annotation_brush: wgpu_text::TextBrush<wgpu_text::glyph_brush::ab_glyph::FontArc>,
+
+    loupe_vertex_buffer: wgpu::Buffer,
+    loupe_buffer: wgpu::Buffer,
+    loupe_bind_group: wgpu::BindGroup,
+    /// The text under the loupe, the loupe is shown while it is set
+    loupe_section: Option<OwnedSection>,
+    /// Separate from the other text as the loupe is drawn over it
+    loupe_brush: wgpu_text::TextBrush<wgpu_text::glyph_brush::ab_glyph::FontArc>,
+
     /// Texture to render the overlay with anti-aliasing
     ms_tex: wgpu::TextureView,
     /// The target to resolve to when rendering the multisampled overlay
@@ -65,6 +118,10 @@ pub struct MonSpecificRendering {
     rect_mode_section: OwnedSection,
     display_mode_section: OwnedSection,
     window_mode_section: OwnedSection,
+    /// Shown instead of the window mode text when picking windows from a list
+    pub window_list_section: Option<OwnedSection>,
+    /// The size and the position of the selection
+    dimensions_section: Option<OwnedSection>,
 }
 
 impl Renderer {
@@ -172,15 +229,16 @@ impl Renderer {
             }],
         });
 
-        let overlay_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Overlay render pipeline layout"),
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
+        let overlay_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Overlay render pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
 
         let overlay_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Overlay render pipeline"),
-            layout: Some(&overlay_layout),
+            layout: Some(&overlay_pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &color_shapes_shader,
                 entry_point: "vs_main",
@@ -213,6 +271,181 @@ impl Renderer {
             multiview: None,
         });
 
+        let annotation_stencil = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::NotEqual,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Replace,
+        };
+
+        let annotation_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Annotation render pipeline"),
+            layout: Some(&overlay_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &color_shapes_shader,
+                entry_point: "vs_main",
+                buffers: &[OverlayVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &color_shapes_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: ANNOTATION_STENCIL_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: annotation_stencil,
+                    back: annotation_stencil,
+                    read_mask: 0xff,
+                    write_mask: 0xff,
+                },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: OVERLAY_MSAA,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        let redact_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Redaction shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../res/redact.wgsl").into()),
+        });
+
+        let redaction_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Redaction bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let redaction_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Redaction pipeline layout"),
+                bind_group_layouts: &[&tex_layout, &redaction_layout],
+                push_constant_ranges: &[],
+            });
+
+        let redaction_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Redaction pipeline"),
+            layout: Some(&redaction_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &redact_shader,
+                entry_point: "vs_main",
+                buffers: &[OverlayVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &redact_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        let loupe_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Loupe shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../res/loupe.wgsl").into()),
+        });
+
+        let loupe_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Loupe bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let loupe_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Loupe pipeline layout"),
+                bind_group_layouts: &[&tex_layout, &loupe_layout],
+                push_constant_ranges: &[],
+            });
+
+        let loupe_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Loupe pipeline"),
+            layout: Some(&loupe_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &loupe_shader,
+                entry_point: "vs_main",
+                buffers: &[OverlayVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &loupe_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
         let shade_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Shade color uniform buffer"),
             contents: bytemuck::cast_slice(&[config.shade_color]),
@@ -249,11 +482,57 @@ impl Renderer {
             tex_sampler,
             tex_vertex_buffer,
             overlay_pipeline,
+            annotation_pipeline,
+            overlay_layout: bind_group_layout,
+            redaction_pipeline,
+            redaction_layout,
+            loupe_pipeline,
+            loupe_layout,
             shade_bind_group,
             sel_bind_group,
         }
     }
 
+    /// Bind group to draw with the given color through the overlay pipeline
+    fn color_bind_group(&self, device: &wgpu::Device, color: Color) -> wgpu::BindGroup {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Annotation color uniform buffer"),
+            contents: bytemuck::cast_slice(&[color]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Annotation bind group"),
+            layout: &self.overlay_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        })
+    }
+
+    /// Uniform buffer and bind group to draw a redaction with through the redaction pipeline,
+    /// the buffer is written before every draw
+    fn redaction_uniform(&self, device: &wgpu::Device) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Redaction uniform buffer"),
+            size: std::mem::size_of::<RedactionUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Redaction bind group"),
+            layout: &self.redaction_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        (buffer, bind_group)
+    }
+
     pub fn render(
         &self,
         encoder: &mut wgpu::CommandEncoder,
@@ -284,6 +563,25 @@ impl Renderer {
             render_pass.set_vertex_buffer(0, self.tex_vertex_buffer.slice(..));
             render_pass.set_bind_group(0, &rendering.bg_bind_group, &[]);
             render_pass.draw(0..6, 0..1);
+
+            // Redactions replace the background, so the shade and the annotations go over them
+            if let Some(scissor) = rendering
+                .annotation_scissor
+                .filter(|_| !rendering.redaction_draws.is_empty())
+            {
+                render_pass.set_pipeline(&self.redaction_pipeline);
+                render_pass.set_scissor_rect(scissor.x, scissor.y, scissor.width, scissor.height);
+                render_pass.set_vertex_buffer(0, rendering.annotation_vertex_buffer.slice(..));
+                render_pass.set_index_buffer(
+                    rendering.annotation_index_buffer.slice(..),
+                    wgpu::IndexFormat::Uint32,
+                );
+                render_pass.set_bind_group(0, &rendering.bg_bind_group, &[]);
+                for (indices, uniform) in &rendering.redaction_draws {
+                    render_pass.set_bind_group(1, &rendering.redaction_uniforms[*uniform].1, &[]);
+                    render_pass.draw_indexed(indices.clone(), 0, 0..1);
+                }
+            }
         }
         // Draw the shade to the multisampling texture
         {
@@ -308,6 +606,46 @@ impl Renderer {
             render_pass.set_bind_group(0, &self.shade_bind_group, &[]);
             render_pass.draw_indexed(0..rendering.shade_index_count, 0, 0..1);
         }
+        // Draw the annotations within the selection to the multisampling texture
+        if let Some(scissor) = rendering.annotation_scissor {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &rendering.ms_tex,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &rendering.annotation_stencil,
+                    depth_ops: None,
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: false,
+                    }),
+                }),
+            });
+            render_pass.set_pipeline(&self.annotation_pipeline);
+            render_pass.set_scissor_rect(scissor.x, scissor.y, scissor.width, scissor.height);
+            render_pass.set_vertex_buffer(0, rendering.annotation_vertex_buffer.slice(..));
+            render_pass.set_index_buffer(
+                rendering.annotation_index_buffer.slice(..),
+                wgpu::IndexFormat::Uint32,
+            );
+            for (i, (indices, color)) in rendering.annotation_draws.iter().enumerate() {
+                // A different reference for each annotation lets them blend with each other
+                render_pass.set_stencil_reference(i as u32 % 255 + 1);
+                let (_, bind_group) = rendering
+                    .annotation_bind_groups
+                    .iter()
+                    .find(|(bind_group_color, _)| bind_group_color == color)
+                    .unwrap();
+                render_pass.set_bind_group(0, bind_group, &[]);
+                render_pass.draw_indexed(indices.clone(), 0, 0..1);
+            }
+        }
         // Draw the selection outline to the multisampling texture, and resolve it to the resolve texture
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -351,13 +689,49 @@ impl Renderer {
             render_pass.draw(0..6, 0..1);
         }
 
-        if let Some(section) = match selection {
+        // The text annotations are cut to the selection like the rest of them
+        if let Some(scissor) = rendering
+            .annotation_scissor
+            .filter(|_| !rendering.text_sections.is_empty())
+        {
+            rendering
+                .annotation_brush
+                .queue(device, queue, rendering.text_sections.iter().collect())
+                .unwrap();
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: surface_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_scissor_rect(scissor.x, scissor.y, scissor.width, scissor.height);
+
+            rendering.annotation_brush.draw(&mut render_pass);
+        }
+
+        let mode_section = match selection {
+            Selection::Window(_) if rendering.window_list_section.is_some() => {
+                rendering.window_list_section.as_ref()
+            }
             Selection::Rectangle(None) => Some(&rendering.rect_mode_section),
             Selection::Display(None) => Some(&rendering.display_mode_section),
             Selection::Window(None) => Some(&rendering.window_mode_section),
             _ => None,
-        } {
-            rendering.brush.queue(device, queue, vec![section]).unwrap();
+        };
+        let sections = mode_section
+            .into_iter()
+            .chain(&rendering.dimensions_section)
+            .collect::<Vec<_>>();
+
+        if !sections.is_empty() {
+            rendering.brush.queue(device, queue, sections).unwrap();
 
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
@@ -374,6 +748,34 @@ impl Renderer {
 
             rendering.brush.draw(&mut render_pass);
         }
+
+        // The loupe goes over everything, wherever the cursor is
+        if let Some(loupe_section) = &rendering.loupe_section {
+            rendering
+                .loupe_brush
+                .queue(device, queue, vec![loupe_section])
+                .unwrap();
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: surface_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.loupe_pipeline);
+            render_pass.set_vertex_buffer(0, rendering.loupe_vertex_buffer.slice(..));
+            render_pass.set_bind_group(0, &rendering.bg_bind_group, &[]);
+            render_pass.set_bind_group(1, &rendering.loupe_bind_group, &[]);
+            render_pass.draw(0..6, 0..1);
+
+            rendering.loupe_brush.draw(&mut render_pass);
+        }
     }
 }
 
@@ -469,6 +871,20 @@ impl MonSpecificRendering {
             mapped_at_creation: false,
         });
 
+        let annotation_vertex_buffer = runtime_data.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: INITIAL_ANNOTATION_VERTICES * std::mem::size_of::<OverlayVertex>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let annotation_index_buffer = runtime_data.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: INITIAL_ANNOTATION_VERTICES * 2 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
         let ms_size = wgpu::Extent3d {
             width: (rect.width * info.scale_factor) as u32,
             height: (rect.height * info.scale_factor) as u32,
@@ -489,6 +905,20 @@ impl MonSpecificRendering {
             })
             .create_view(&wgpu::TextureViewDescriptor::default());
 
+        let annotation_stencil = runtime_data
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: ms_size,
+                mip_level_count: 1,
+                sample_count: OVERLAY_MSAA,
+                dimension: wgpu::TextureDimension::D2,
+                format: ANNOTATION_STENCIL_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
         let ms_resolve_target_tex = runtime_data
             .device
             .create_texture(&wgpu::TextureDescriptor {
@@ -523,6 +953,46 @@ impl MonSpecificRendering {
                 ],
             });
 
+        let loupe_vertex_buffer = runtime_data.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 6 * std::mem::size_of::<OverlayVertex>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let loupe_buffer = runtime_data.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: std::mem::size_of::<LoupeUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let loupe_bind_group = runtime_data
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &runtime_data.renderer.as_ref().unwrap().loupe_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: loupe_buffer.as_entire_binding(),
+                }],
+            });
+
+        let loupe_brush = wgpu_text::BrushBuilder::using_font(runtime_data.font.clone()).build(
+            &runtime_data.device,
+            (rect.width * info.scale_factor) as u32,
+            (rect.height * info.scale_factor) as u32,
+            format,
+        );
+
+        let annotation_brush = wgpu_text::BrushBuilder::using_font(runtime_data.font.clone())
+            .build(
+                &runtime_data.device,
+                (rect.width * info.scale_factor) as u32,
+                (rect.height * info.scale_factor) as u32,
+                format,
+            );
+
         let brush = wgpu_text::BrushBuilder::using_font(runtime_data.font.clone()).build(
             &runtime_data.device,
             (rect.width * info.scale_factor) as u32,
@@ -565,10 +1035,26 @@ impl MonSpecificRendering {
 
         Self {
             bg_bind_group,
+            bg_size: [background.width(), background.height()],
             shade_vertex_buffer,
             shade_index_buffer,
             sel_vertex_buffer,
             sel_index_buffer,
+            annotation_vertex_buffer,
+            annotation_index_buffer,
+            annotation_stencil,
+            annotation_draws: Vec::new(),
+            annotation_bind_groups: Vec::new(),
+            redaction_draws: Vec::new(),
+            redaction_uniforms: Vec::new(),
+            annotation_scissor: None,
+            text_sections: Vec::new(),
+            annotation_brush,
+            loupe_vertex_buffer,
+            loupe_buffer,
+            loupe_bind_group,
+            loupe_section: None,
+            loupe_brush,
             ms_tex,
             ms_resolve_target_tex,
             ms_bind_group,
@@ -576,6 +1062,8 @@ impl MonSpecificRendering {
             rect_mode_section,
             display_mode_section,
             window_mode_section,
+            window_list_section: None,
+            dimensions_section: None,
             shade_index_count: 0,
             sel_index_count: 0,
         }
@@ -584,13 +1072,13 @@ impl MonSpecificRendering {
     pub fn update_overlay_vertices(
         &mut self,
         mon_rect: &Rect<i32>,
-        wl_surface: &wl_surface::WlSurface,
         selection: &Selection,
         config: &Config,
         queue: &wgpu::Queue,
     ) {
         let flatten_selection = selection.flattened();
 
+        #[allow(clippy::type_complexity)]
         let (shade_vertices, shade_indices, sel_vertices, sel_indices): (
             Vec<[f32; 2]>,
             Vec<u32>,
@@ -652,7 +1140,7 @@ impl MonSpecificRendering {
                 }
             }
             Selection::Display(Some(selection)) => {
-                if selection.wl_surface == *wl_surface {
+                if selection.rect == *mon_rect {
                     self.shade_index_count = 0;
                     self.sel_index_count = 24;
 
@@ -711,6 +1199,484 @@ impl MonSpecificRendering {
             bytemuck::cast_slice(&shade_indices),
         );
     }
+
+    /// Upload the annotations that are visible on this monitor
+    pub fn update_annotations(
+        &mut self,
+        mon_rect: &Rect<i32>,
+        scale_factor: i32,
+        controller: &SelectionController,
+        renderer: &Renderer,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        // Only the parts within the selection end up in the screenshot
+        self.annotation_scissor = controller
+            .selected_rect()
+            .filter(|_| !controller.annotations.is_empty())
+            .and_then(|rect| rect.constrain(mon_rect))
+            .map(|rect| {
+                let rect = rect.to_local(mon_rect);
+                // The scissor has to stay within the surface
+                Rect::new(
+                    (rect.x * scale_factor) as u32,
+                    (rect.y * scale_factor) as u32,
+                    (rect.width.min(mon_rect.width - rect.x) * scale_factor) as u32,
+                    (rect.height.min(mon_rect.height - rect.y) * scale_factor) as u32,
+                )
+            });
+
+        self.annotation_draws.clear();
+        self.redaction_draws.clear();
+        self.text_sections.clear();
+        if self.annotation_scissor.is_none() {
+            return;
+        }
+
+        for (i, annotation) in controller.annotations.iter().enumerate() {
+            if let Shape::Counter { center } = annotation.shape {
+                self.text_sections.push(
+                    OwnedSection::default()
+                        .add_text(
+                            OwnedText::new(
+                                annotation::counter_number(&controller.annotations, i).to_string(),
+                            )
+                            .with_scale(
+                                annotation::counter_text_size(annotation.width)
+                                    * scale_factor as f32,
+                            )
+                            .with_color(annotation::counter_text_color(&annotation.color)),
+                        )
+                        .with_layout(
+                            Layout::default()
+                                .h_align(HorizontalAlign::Center)
+                                .v_align(VerticalAlign::Center),
+                        )
+                        .with_screen_position((
+                            ((center.0 - mon_rect.x) * scale_factor) as f32,
+                            ((center.1 - mon_rect.y) * scale_factor) as f32,
+                        )),
+                );
+            }
+
+            let Shape::Text { position, text } = &annotation.shape else {
+                continue;
+            };
+
+            let mut text = text.clone();
+            if i == controller.annotations.len() - 1 {
+                if let Some(caret) = controller.text_caret {
+                    text.insert(caret, '|');
+                }
+            }
+
+            self.text_sections.push(
+                OwnedSection::default()
+                    .add_text(
+                        OwnedText::new(text)
+                            .with_scale(annotation.width * scale_factor as f32)
+                            .with_color(annotation.color),
+                    )
+                    .with_screen_position((
+                        ((position.0 - mon_rect.x) * scale_factor) as f32,
+                        ((position.1 - mon_rect.y) * scale_factor) as f32,
+                    )),
+            );
+        }
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for Annotation { color, .. } in &controller.annotations {
+            if !self
+                .annotation_bind_groups
+                .iter()
+                .any(|(bind_group_color, _)| bind_group_color == color)
+            {
+                self.annotation_bind_groups
+                    .push((*color, renderer.color_bind_group(device, *color)));
+            }
+        }
+
+        // Texels of the background per logical pixel, and per pixel of the surface
+        let texel_scale = [
+            self.bg_size[0] as f32 / mon_rect.width as f32,
+            self.bg_size[1] as f32 / mon_rect.height as f32,
+        ];
+        let selected_rect = controller.selected_rect().unwrap_or_default();
+
+        for annotation in &controller.annotations {
+            let redaction = match annotation.shape {
+                Shape::Redaction { .. } => {
+                    match annotation.to_pixel_redaction(mon_rect, &selected_rect, texel_scale) {
+                        Some(redaction) => Some(redaction),
+                        // Not on this monitor
+                        None => continue,
+                    }
+                }
+                _ => None,
+            };
+
+            let (mesh_vertices, mesh_indices) = match annotation.shape {
+                // Filled like the handles of the selection
+                Shape::Counter { center } => Circle::new(
+                    center.0 - mon_rect.x,
+                    center.1 - mon_rect.y,
+                    annotation.width.round() as i32,
+                )
+                .to_vertices(mon_rect.width, mon_rect.height),
+                _ => {
+                    let mesh = annotation.to_mesh();
+                    let vertices = mesh
+                        .vertices
+                        .into_iter()
+                        .map(|vertex| {
+                            [vertex[0] - mon_rect.x as f32, vertex[1] - mon_rect.y as f32]
+                                .to_render(mon_rect.width, mon_rect.height)
+                        })
+                        .collect();
+                    (vertices, mesh.indices)
+                }
+            };
+            let first_index = indices.len() as u32;
+
+            indices.extend(
+                mesh_indices
+                    .iter()
+                    .map(|index| index + vertices.len() as u32),
+            );
+            vertices.extend(mesh_vertices.into_iter().map(|pos| OverlayVertex { pos }));
+
+            let draw = first_index..indices.len() as u32;
+            match redaction {
+                Some(redaction) => {
+                    let uniform = RedactionUniform {
+                        bounds: [
+                            redaction.min[0].max(0),
+                            redaction.min[1].max(0),
+                            redaction.max[0].min(self.bg_size[0] as i32),
+                            redaction.max[1].min(self.bg_size[1] as i32),
+                        ],
+                        origin: redaction.origin,
+                        scale: [
+                            texel_scale[0] / scale_factor as f32,
+                            texel_scale[1] / scale_factor as f32,
+                        ],
+                        size: redaction.size,
+                        mode: match redaction.kind {
+                            Redaction::Blur => 0,
+                            Redaction::Pixelate => 1,
+                        },
+                        _padding: [0; 2],
+                    };
+
+                    let index = self.redaction_draws.len();
+                    if index == self.redaction_uniforms.len() {
+                        self.redaction_uniforms
+                            .push(renderer.redaction_uniform(device));
+                    }
+                    queue.write_buffer(
+                        &self.redaction_uniforms[index].0,
+                        0,
+                        bytemuck::cast_slice(&[uniform]),
+                    );
+                    self.redaction_draws.push((draw, index));
+                }
+                None => self.annotation_draws.push((draw, annotation.color)),
+            }
+        }
+
+        let vertex_size = std::mem::size_of_val(vertices.as_slice()) as u64;
+        if vertex_size > self.annotation_vertex_buffer.size() {
+            self.annotation_vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: vertex_size.next_power_of_two(),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+        }
+        let index_size = std::mem::size_of_val(indices.as_slice()) as u64;
+        if index_size > self.annotation_index_buffer.size() {
+            self.annotation_index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: index_size.next_power_of_two(),
+                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+        }
+
+        queue.write_buffer(
+            &self.annotation_vertex_buffer,
+            0,
+            bytemuck::cast_slice(&vertices),
+        );
+        queue.write_buffer(
+            &self.annotation_index_buffer,
+            0,
+            bytemuck::cast_slice(&indices),
+        );
+    }
+
+    /// Label the selection with its size and position, as configured. The physical pixels are
+    /// those of the image of the monitor, trimmed to the fixed size like the screenshot.
+    pub fn update_dimensions(
+        &mut self,
+        mon_rect: &Rect<i32>,
+        scale_factor: i32,
+        image_scale: f32,
+        fixed_size: Option<Size>,
+        selection: &Selection,
+        config: &Config,
+    ) {
+        let Selection::Rectangle(Some(selection)) = selection.flattened() else {
+            self.dimensions_section = None;
+            return;
+        };
+        let rect = selection.extents.to_rect();
+
+        let text = format_dimensions(&config.dimensions_format, &rect, image_scale, fixed_size);
+        let text_size = (config.mode_text_size * scale_factor) as f32 / 2.0;
+        let margin = (config.handle_radius * scale_factor) as f32;
+        // Roughly the height of the text, to tell whether it fits above or below the selection
+        let text_height = text.lines().count() as f32 * text_size * 1.2 + margin;
+
+        let local = rect.to_local(mon_rect);
+        let (left, top) = (
+            (local.x * scale_factor) as f32,
+            (local.y * scale_factor) as f32,
+        );
+        let bottom = ((local.y + local.height) * scale_factor) as f32;
+        let surface_height = (mon_rect.height * scale_factor) as f32;
+
+        let position = match config.dimensions_position {
+            DimensionsPosition::Hidden => {
+                self.dimensions_section = None;
+                return;
+            }
+            DimensionsPosition::Above if top - text_height >= 0.0 => DimensionsPosition::Above,
+            DimensionsPosition::Above | DimensionsPosition::Below
+                if bottom + text_height <= surface_height =>
+            {
+                DimensionsPosition::Below
+            }
+            DimensionsPosition::Below if top - text_height >= 0.0 => DimensionsPosition::Above,
+            DimensionsPosition::Above | DimensionsPosition::Below => DimensionsPosition::Inside,
+            position => position,
+        };
+
+        let (screen_position, layout) = match position {
+            DimensionsPosition::Above => (
+                (left, top - margin),
+                Layout::default().v_align(VerticalAlign::Bottom),
+            ),
+            DimensionsPosition::Below => ((left, bottom + margin), Layout::default()),
+            DimensionsPosition::Center => (
+                (
+                    ((local.x * 2 + local.width) * scale_factor) as f32 / 2.0,
+                    ((local.y * 2 + local.height) * scale_factor) as f32 / 2.0,
+                ),
+                Layout::default()
+                    .h_align(HorizontalAlign::Center)
+                    .v_align(VerticalAlign::Center),
+            ),
+            _ => ((left + margin, top + margin), Layout::default()),
+        };
+
+        self.dimensions_section = Some(
+            OwnedSection::default()
+                .add_text(
+                    OwnedText::new(text)
+                        .with_scale(text_size)
+                        .with_color(config.text_color),
+                )
+                .with_layout(layout)
+                .with_screen_position(screen_position),
+        );
+    }
+
+    /// Show the loupe around the cursor if it is on this monitor, hide it otherwise
+    pub fn update_loupe(
+        &mut self,
+        mon_rect: &Rect<i32>,
+        scale_factor: i32,
+        cursor: Option<(i32, i32)>,
+        image: &DynamicImage,
+        config: &Config,
+        queue: &wgpu::Queue,
+    ) {
+        let Some(cursor) = cursor.filter(|cursor| {
+            cursor.0 >= mon_rect.x
+                && cursor.0 < mon_rect.x + mon_rect.width
+                && cursor.1 >= mon_rect.y
+                && cursor.1 < mon_rect.y + mon_rect.height
+        }) else {
+            self.loupe_section = None;
+            return;
+        };
+
+        let surface_size = [
+            mon_rect.width * scale_factor,
+            mon_rect.height * scale_factor,
+        ];
+        let texel_scale = image.width() as f32 / mon_rect.width as f32;
+        let local = [cursor.0 - mon_rect.x, cursor.1 - mon_rect.y];
+        let texel = [
+            ((local[0] as f32 * texel_scale) as u32).min(image.width() - 1),
+            ((local[1] as f32 * texel_scale) as u32).min(image.height() - 1),
+        ];
+
+        let size = (LOUPE_SIZE * scale_factor) as f32;
+        let text_size = (LOUPE_TEXT_SIZE * scale_factor) as f32;
+        let info_height = (text_size * 3.0).round();
+        let offset = (LOUPE_OFFSET * scale_factor) as f32;
+
+        // Next to the cursor, flipped to the other side near the edges
+        let pointer = [
+            (local[0] * scale_factor) as f32,
+            (local[1] * scale_factor) as f32,
+        ];
+        let mut origin = [pointer[0] + offset, pointer[1] + offset];
+        if origin[0] + size > surface_size[0] as f32 {
+            origin[0] = pointer[0] - offset - size;
+        }
+        if origin[1] + size + info_height > surface_size[1] as f32 {
+            origin[1] = pointer[1] - offset - size - info_height;
+        }
+
+        let uniform = LoupeUniform {
+            origin,
+            center: [texel[0] as i32, texel[1] as i32],
+            size,
+            // The zoom is relative to how large the pixels appear on the screen
+            zoom: config.magnifier_zoom as f32 * scale_factor as f32 / texel_scale,
+            info_height,
+            _padding: 0.0,
+            border_color: config.selection_color,
+        };
+        queue.write_buffer(&self.loupe_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let (left, top) = (origin[0], origin[1]);
+        let (right, bottom) = (origin[0] + size, origin[1] + size + info_height);
+        let vertices = [
+            [left, top],
+            [left, bottom],
+            [right, top],
+            [right, bottom],
+            [right, top],
+            [left, bottom],
+        ]
+        .map(|pos| OverlayVertex {
+            pos: pos.to_render(surface_size[0], surface_size[1]),
+        });
+        queue.write_buffer(
+            &self.loupe_vertex_buffer,
+            0,
+            bytemuck::cast_slice(&vertices),
+        );
+
+        let [r, g, b, _] = image.get_pixel(texel[0], texel[1]).0;
+        self.loupe_section = Some(
+            OwnedSection::default()
+                .add_text(
+                    OwnedText::new(format!(
+                        "{}, {}\n#{:02X}{:02X}{:02X} ({}, {}, {})",
+                        cursor.0, cursor.1, r, g, b, r, g, b
+                    ))
+                    .with_scale(text_size)
+                    .with_color(config.text_color),
+                )
+                .with_screen_position((left + text_size / 2.0, top + size + text_size / 4.0)),
+        );
+    }
+}
+
+/// Fill in the placeholders of the dimensions format. The physical pixels are those of an
+/// image with the given scale, rounded like [`crate::capture::crop`] does.
+pub fn format_dimensions(
+    format: &str,
+    rect: &Rect<i32>,
+    image_scale: f32,
+    fixed_size: Option<Size>,
+) -> String {
+    let physical = |value: i32| (value as f32 * image_scale) as i32;
+    let (mut width, mut height) = (physical(rect.width), physical(rect.height));
+    if let Some(size) = fixed_size {
+        width = width.min(size.width);
+        height = height.min(size.height);
+    }
+
+    format
+        .replace("{physical_width}", &width.to_string())
+        .replace("{physical_height}", &height.to_string())
+        .replace("{physical_x}", &physical(rect.x).to_string())
+        .replace("{physical_y}", &physical(rect.y).to_string())
+        .replace("{width}", &rect.width.to_string())
+        .replace("{height}", &rect.height.to_string())
+        .replace("{x}", &rect.x.to_string())
+        .replace("{y}", &rect.y.to_string())
+}
+
+/// The searchable list to pick a window from, used in window mode when the
+/// compositor doesn't tell where the windows are
+pub fn window_list_section(
+    query: &str,
+    windows: &[&WindowDescriptor],
+    selection: &Selection,
+    config: &Config,
+    info: &OutputInfo,
+    rect: &Rect<i32>,
+) -> OwnedSection {
+    let text_size = (config.mode_text_size * info.scale_factor) as f32;
+
+    let selected = match selection {
+        Selection::Window(Some(selected)) => windows.iter().position(|window| *window == selected),
+        _ => None,
+    };
+    // Scroll the list to keep the selected window visible
+    let skip = selected.map_or(0, |selected| {
+        (selected + 1).saturating_sub(WINDOW_LIST_LENGTH)
+    });
+
+    let mut section = OwnedSection::default()
+        .add_text(
+            OwnedText::new("WINDOW MODE\n")
+                .with_scale(text_size)
+                .with_color(config.text_color),
+        )
+        .add_text(
+            OwnedText::new(format!("Search: {}\n", query))
+                .with_scale(text_size / 2.0)
+                .with_color(config.text_color),
+        );
+
+    for (i, window) in windows
+        .iter()
+        .enumerate()
+        .skip(skip)
+        .take(WINDOW_LIST_LENGTH)
+    {
+        let color = if Some(i) == selected {
+            config.selection_color
+        } else {
+            config.text_color
+        };
+
+        section = section.add_text(
+            OwnedText::new(format!("\n{} ({})", window.title, window.class))
+                .with_scale(text_size / 2.0)
+                .with_color(color),
+        );
+    }
+
+    section
+        .with_layout(
+            Layout::default()
+                .h_align(HorizontalAlign::Center)
+                .v_align(VerticalAlign::Center),
+        )
+        .with_screen_position((
+            (rect.width * info.scale_factor) as f32 / 2.0,
+            (rect.height * info.scale_factor) as f32 / 2.0,
+        ))
 }
 
 #[derive(Clone, Copy)]
@@ -828,6 +1794,31 @@ impl TexVertex {
     }
 }
 
+/// Matches the `Loupe` struct of `res/loupe.wgsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LoupeUniform {
+    origin: [f32; 2],
+    center: [i32; 2],
+    size: f32,
+    zoom: f32,
+    info_height: f32,
+    _padding: f32,
+    border_color: Color,
+}
+
+/// Matches the `Redaction` struct of `res/redact.wgsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct RedactionUniform {
+    bounds: [i32; 4],
+    origin: [i32; 2],
+    scale: [f32; 2],
+    size: i32,
+    mode: u32,
+    _padding: [u32; 2],
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct OverlayVertex {
@@ -872,3 +1863,32 @@ impl OverlayVertex {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_dimensions() {
+        let rect = Rect::new(-20, 30, 400, 300);
+
+        assert_eq!(
+            format_dimensions(&Config::default().dimensions_format, &rect, 2.0, None),
+            "400×300 at -20,30\n800×600 at -40,60 physical"
+        );
+        assert_eq!(
+            format_dimensions("{width}x{height}", &rect, 1.0, None),
+            "400x300"
+        );
+
+        // What --size 200x100 selects at a fractional scale, and what it saves
+        let rect = Rect::new(633, 167, 134, 67);
+        let format = "{physical_width}x{physical_height}";
+        assert_eq!(format_dimensions(format, &rect, 1.5, None), "201x100");
+        let size = Size {
+            width: 200,
+            height: 100,
+        };
+        assert_eq!(format_dimensions(format, &rect, 1.5, Some(size)), "200x100");
+    }
+}
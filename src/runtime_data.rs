@@ -1,4 +1,4 @@
-use std::{fs, io::Cursor, process::Command};
+use std::{fs, io};
 
 use fontconfig::Fontconfig;
 use image::DynamicImage;
@@ -9,7 +9,7 @@ use smithay_client_toolkit::{
     reexports::client::{
         globals::GlobalList,
         protocol::{wl_keyboard, wl_pointer, wl_surface},
-        QueueHandle,
+        Connection, QueueHandle,
     },
     registry::RegistryState,
     seat::{pointer::ThemedPointer, SeatState},
@@ -18,18 +18,15 @@ use smithay_client_toolkit::{
 };
 
 use crate::{
-    handles,
-    rendering::Renderer,
-    traits::{Contains, DistanceTo},
-    types::{
-        Args, ExitState, MonitorIdentification, RectangleSelection, SelectionModifier,
-        SelectionState,
-    },
+    controller::SelectionController,
+    rendering::{window_list_section, Renderer},
+    types::{Config, Monitor, MonitorIdentification, Options, Selection},
     window::{
-        hyprland::HyprlandBackend, CompositorBackend, FindWindowExt, InitializeBackend,
+        foreign_toplevel::ForeignToplevelBackend, hyprland::HyprlandBackend, niri::NiriBackend,
+        sway::SwayBackend, CompositorBackend, CompositorError, FindWindowExt, InitializeBackend,
         WindowDescriptor,
     },
-    Config, Monitor, Rect, Selection,
+    Error,
 };
 
 /// The main data worked on at runtime
@@ -49,17 +46,14 @@ pub struct RuntimeData {
     pub pointer_surface: wl_surface::WlSurface,
     pub themed_pointer: Option<ThemedPointer>,
 
-    /// Combined area of all monitors
-    pub area: Rect<i32>,
     /// The scale factor of the screenshot image
     pub scale_factor: f32,
-    pub selection: Selection,
+    pub controller: SelectionController,
     pub monitors: Vec<Monitor>,
     pub config: Config,
     pub font: wgpu_text::glyph_brush::ab_glyph::FontArc,
     pub image: DynamicImage,
-    pub exit: ExitState,
-    pub args: Args,
+    pub options: Options,
 
     pub instance: wgpu::Instance,
     pub device: wgpu::Device,
@@ -67,39 +61,93 @@ pub struct RuntimeData {
     pub queue: wgpu::Queue,
 
     pub renderer: Option<Renderer>,
-
-    pub compositor_backend: Option<Box<dyn CompositorBackend>>,
-    pub windows: Vec<WindowDescriptor>,
 }
 
 impl RuntimeData {
-    pub fn get_preferred_backend() -> Option<Box<dyn CompositorBackend>> {
-        HyprlandBackend::try_new().ok()
+    pub fn get_preferred_backend(
+        conn: &Connection,
+        globals: &GlobalList,
+    ) -> Option<Box<dyn CompositorBackend>> {
+        HyprlandBackend::try_new()
+            .or_else(|_| SwayBackend::try_new())
+            .or_else(|_| NiriBackend::try_new())
+            .or_else(|_| ForeignToplevelBackend::try_new(conn, globals))
+            .ok()
     }
 
-    pub fn new(qh: &QueueHandle<Self>, globals: &GlobalList, mut args: Args) -> Self {
-        let output = Command::new(args.grim.as_ref().unwrap_or(&"grim".to_string()))
-            .arg("-t")
-            .arg("ppm")
-            .arg("-")
-            .output()
-            .expect("Failed to run grim command!")
-            .stdout;
+    /// The windows and the selection resulting from the window related arguments, and whether
+    /// the window under the cursor is only known once the pointer enters a surface
+    fn initial_window_selection(
+        compositor_backend: &dyn CompositorBackend,
+        options: &mut Options,
+    ) -> Result<(Selection, Vec<WindowDescriptor>, bool), CompositorError> {
+        let windows = compositor_backend.get_all_windows()?;
+        let mut select_on_enter = false;
+
+        let selection = {
+            if let Some(search_param) = options.window_search.take() {
+                Selection::from_window(windows.find_by_search_param(search_param).cloned())
+            } else if options.window_under_cursor {
+                match compositor_backend.get_mouse_position()? {
+                    Some(mouse_pos) => {
+                        Selection::from_window(windows.find_by_position(&mouse_pos).cloned())
+                    }
+                    None if windows.iter().any(|window| window.rect.is_some()) => {
+                        select_on_enter = true;
+                        Selection::default()
+                    }
+                    None => {
+                        log::warn!(
+                            "The compositor reports neither the cursor nor where the windows are, ignoring --window-under-cursor"
+                        );
+                        Selection::default()
+                    }
+                }
+            } else if options.active_window {
+                Selection::from_window(compositor_backend.get_focused()?)
+            } else {
+                Selection::default()
+            }
+        };
 
-        let image = image::io::Reader::with_format(Cursor::new(output), image::ImageFormat::Pnm)
-            .decode()
-            .expect("Failed to parse grim image!");
+        Ok((selection, windows, select_on_enter))
+    }
 
-        let config = Config::load().unwrap_or_default();
+    pub fn new(
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        globals: &GlobalList,
+        mut options: Options,
+    ) -> Result<Self, Error> {
+        let config = Config::load().unwrap_or_else(|why| {
+            // Not having a config at all is fine
+            if !matches!(
+                why.downcast_ref::<io::Error>(),
+                Some(why) if why.kind() == io::ErrorKind::NotFound
+            ) {
+                log::warn!("Failed to load the config, using the defaults: {}", why);
+            }
+            Config::default()
+        });
 
-        let fc = Fontconfig::new().expect("Failed to init FontConfig");
+        let fc = Fontconfig::new().ok_or(Error::Font("Failed to init FontConfig".to_string()))?;
 
         let fc_font = fc
             .find(&config.font_family, None)
-            .expect("Failed to find font");
-
-        let compositor_state =
-            CompositorState::bind(globals, qh).expect("wl_compositor is not available");
+            .ok_or_else(|| Error::Font(format!("No font matches \"{}\"", config.font_family)))?;
+        let font = fs::read(&fc_font.path)
+            .map_err(|why| format!("{}: {}", fc_font.path.display(), why))
+            .and_then(|data| {
+                wgpu_text::glyph_brush::ab_glyph::FontArc::try_from_vec(data)
+                    .map_err(|_| format!("{}: Invalid font data", fc_font.path.display()))
+            })
+            .map_err(Error::Font)?;
+
+        let compositor_state = CompositorState::bind(globals, qh)
+            .map_err(|_| Error::MissingProtocol("wl_compositor"))?;
+        let layer_state =
+            LayerShell::bind(globals, qh).map_err(|_| Error::MissingProtocol("wlr-layer-shell"))?;
+        let shm_state = Shm::bind(globals, qh).map_err(|_| Error::MissingProtocol("wl_shm"))?;
 
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends: wgpu::Backends::all(),
@@ -113,7 +161,7 @@ impl RuntimeData {
                 compatible_surface: None,
                 ..Default::default()
             }))
-            .unwrap();
+            .ok_or(Error::Gpu("No suitable graphics adapter".to_string()))?;
 
         let (device, queue) = pollster::block_on(adapter.request_device(
             &wgpu::DeviceDescriptor {
@@ -123,78 +171,61 @@ impl RuntimeData {
             },
             None,
         ))
-        .unwrap();
+        .map_err(|why| Error::Gpu(why.to_string()))?;
 
-        let compositor_backend = Self::get_preferred_backend();
-
-        let mut selection = Selection::default();
-        let mut windows = Vec::default();
-        let mut exit = ExitState::None;
-
-        if let Some(ref compositor_backend) = compositor_backend {
-            (selection, windows, exit) = {
-                let windows = compositor_backend.get_all_windows();
+        let mut controller = SelectionController::new(&config);
+        // The arguments take precedence over the config
+        if let Some(aspect) = options.aspect {
+            controller.aspect = Some(aspect);
+            controller.fixed_size = None;
+        }
+        if let Some(size) = options.size {
+            controller.fixed_size = Some(size);
+        }
 
-                let selection = {
-                    if let Some(search_param) = args.window_search.take() {
-                        Selection::from_window(windows.find_by_search_param(search_param).cloned())
-                    } else if args.window_under_cursor {
-                        let mouse_pos = compositor_backend.get_mouse_position();
-                        Selection::from_window(windows.find_by_position(&mouse_pos).cloned())
-                    } else if args.active_window {
-                        Selection::from_window(compositor_backend.get_focused())
-                    } else {
-                        Selection::default()
-                    }
-                };
-
-                if !args.auto_capture {
-                    (selection, windows, ExitState::None)
-                } else if let Selection::Rectangle(Some(rect_sel)) = selection.flattened() {
-                    (
-                        selection,
-                        windows,
-                        ExitState::ExitWithSelection(rect_sel.extents.to_rect()),
-                    )
-                } else {
-                    // TODO: Auto-capture for monitors
-                    (selection, windows, ExitState::None)
+        if let Some(backend) = Self::get_preferred_backend(conn, globals) {
+            match Self::initial_window_selection(backend.as_ref(), &mut options) {
+                Ok((selection, windows, select_on_enter)) => {
+                    controller.selection = selection;
+                    controller.windows = windows;
+                    controller.window_mode = true;
+                    controller.select_window_on_enter = select_on_enter;
+                    controller.auto_capture = options.auto_capture;
                 }
-            };
+                Err(why) => {
+                    log::error!(
+                        "Failed to get the windows from the compositor, window selection is unavailable: {}",
+                        why
+                    );
+                }
+            }
         }
 
-        RuntimeData {
+        Ok(RuntimeData {
             registry_state: RegistryState::new(globals),
             seat_state: SeatState::new(globals, qh),
             output_state: OutputState::new(globals, qh),
             compositor_state,
-            layer_state: LayerShell::bind(globals, qh).expect("layer shell is not available"),
-            shm_state: Shm::bind(globals, qh).expect("wl_shm is not available"),
-            selection,
+            layer_state,
+            shm_state,
+            controller,
             config,
-            area: Rect::default(),
             monitors: Vec::new(),
             // Set later
             scale_factor: 0.0,
-            image,
+            image: DynamicImage::default(),
             keyboard: None,
             pointer: None,
             themed_pointer: None,
-            exit,
-            args,
+            options,
             pointer_surface,
             instance,
             adapter,
             device,
             queue,
             renderer: None,
-            font: wgpu_text::glyph_brush::ab_glyph::FontArc::try_from_vec(
-                fs::read(fc_font.path).expect("Failed to load font"),
-            )
-            .expect("Invalid font data"),
-            compositor_backend,
-            windows,
-        }
+            font,
+        })
     }
 
     pub fn draw(&mut self, identification: MonitorIdentification, qh: &QueueHandle<Self>) {
@@ -218,11 +249,52 @@ impl RuntimeData {
         if let Some(rendering) = &mut monitor.rendering {
             rendering.update_overlay_vertices(
                 &monitor.rect,
-                &monitor.wl_surface,
-                &self.selection,
+                &self.controller.selection,
                 &self.config,
                 &self.queue,
             );
+            rendering.update_annotations(
+                &monitor.rect,
+                monitor.output_info.scale_factor,
+                &self.controller,
+                renderer,
+                &self.device,
+                &self.queue,
+            );
+
+            rendering.update_dimensions(
+                &monitor.rect,
+                monitor.output_info.scale_factor,
+                monitor.image.width() as f32 / monitor.rect.width as f32,
+                // Only rectangles are trimmed to it
+                self.controller
+                    .fixed_size
+                    .filter(|_| matches!(self.controller.selection, Selection::Rectangle(_))),
+                &self.controller.selection,
+                &self.config,
+            );
+            rendering.update_loupe(
+                &monitor.rect,
+                monitor.output_info.scale_factor,
+                self.controller.cursor.filter(|_| self.controller.magnifier),
+                &monitor.image,
+                &self.config,
+                &self.queue,
+            );
+
+            rendering.window_list_section = match self.controller.selection {
+                Selection::Window(_) if self.controller.window_list_mode() => {
+                    Some(window_list_section(
+                        &self.controller.window_query,
+                        &self.controller.window_list(),
+                        &self.controller.selection,
+                        &self.config,
+                        &monitor.output_info,
+                        &monitor.rect,
+                    ))
+                }
+                _ => None,
+            };
         }
 
         let surface_texture = monitor.surface.get_current_texture().unwrap();
@@ -238,7 +310,7 @@ impl RuntimeData {
             &mut encoder,
             &texture_view,
             monitor,
-            &self.selection,
+            &self.controller.selection,
             &self.device,
             &self.queue,
         );
@@ -252,31 +324,4 @@ impl RuntimeData {
         surface_texture.present();
         monitor.wl_surface.commit();
     }
-
-    pub fn process_selection_handles(
-        rect_sel: &mut Option<RectangleSelection>,
-        global_pos: (i32, i32),
-        handle_radius: i32,
-    ) -> SelectionState {
-        if let Some(selection) = rect_sel {
-            for (x, y, modifier) in handles!(selection.extents) {
-                if global_pos.distance_to(&(*x, *y)) <= handle_radius {
-                    selection.modifier = Some(*modifier);
-                    selection.active = true;
-                    return SelectionState::HandlesChanged;
-                }
-            }
-            if selection.extents.to_rect().contains(&global_pos) {
-                selection.modifier = Some(SelectionModifier::Center(
-                    global_pos.0,
-                    global_pos.1,
-                    selection.extents,
-                ));
-                selection.active = true;
-                return SelectionState::CenterChanged;
-            }
-        }
-
-        SelectionState::Unchanged
-    }
 }
@@ -9,8 +9,8 @@ use smithay_client_toolkit::{
 };
 
 use crate::{
+    controller::{self, InputEvent, Key},
     runtime_data::RuntimeData,
-    types::{ExitState, Selection},
 };
 
 delegate_keyboard!(RuntimeData);
@@ -46,55 +46,30 @@ impl KeyboardHandler for RuntimeData {
         _: u32,
         event: KeyEvent,
     ) {
-        match event.keysym {
-            // Exit without copying/saving
-            keysyms::XKB_KEY_Escape => self.exit = ExitState::ExitOnly,
-            // Switch selection mode
-            keysyms::XKB_KEY_Tab => match &self.selection {
-                Selection::Rectangle(_) => self.selection = Selection::Display(None),
-                Selection::Display(_) => {
-                    if self.compositor_backend.is_some() {
-                        self.selection = Selection::Window(None)
-                    } else {
-                        self.selection = Selection::Rectangle(None)
-                    }
+        let key = match event.keysym {
+            keysyms::XKB_KEY_Escape => Key::Escape,
+            keysyms::XKB_KEY_Tab => Key::Tab,
+            keysyms::XKB_KEY_Return => Key::Return,
+            keysyms::XKB_KEY_Up => Key::Up,
+            keysyms::XKB_KEY_Down => Key::Down,
+            keysyms::XKB_KEY_Left => Key::Left,
+            keysyms::XKB_KEY_Right => Key::Right,
+            keysyms::XKB_KEY_BackSpace => Key::Backspace,
+            keysyms::XKB_KEY_Delete => Key::Delete,
+            // The letter of the shortcut instead of the control character it types
+            _ if self.controller.modifiers.ctrl => {
+                match char::from_u32(event.keysym).filter(char::is_ascii_alphabetic) {
+                    Some(letter) => Key::Text(letter.to_ascii_lowercase().to_string()),
+                    None => return,
                 }
-                Selection::Window(_) => self.selection = Selection::Rectangle(None),
+            }
+            _ => match event.utf8 {
+                Some(text) if !text.chars().any(char::is_control) => Key::Text(text),
+                _ => return,
             },
-            // Exit with save if a valid selection exists
-            keysyms::XKB_KEY_Return => {
-                let flattened_selection = self.selection.flattened();
-                match flattened_selection {
-                    Selection::Rectangle(Some(selection)) => {
-                        let mut rect = selection.extents.to_rect();
-                        // Alter coordinate space so the rect can be used to crop from the original image
-                        rect.x -= self.area.x;
-                        rect.y -= self.area.y;
-
-                        self.exit = ExitState::ExitWithSelection(rect)
-                    }
-                    Selection::Display(Some(selection)) => {
-                        let monitor = self
-                            .monitors
-                            .iter()
-                            .find(|monitor| monitor.wl_surface == selection.wl_surface)
-                            .unwrap();
-
-                        let mut rect = monitor.rect;
+        };
 
-                        rect.x -= self.area.x;
-                        rect.y -= self.area.y;
-
-                        self.exit = ExitState::ExitWithSelection(rect)
-                    }
-                    Selection::Window(_) => unreachable!(
-                        "Window selection should have been flattened into Rectangle selection"
-                    ),
-                    _ => (),
-                }
-            }
-            _ => (),
-        }
+        self.controller.handle_event(InputEvent::Key(key));
     }
 
     fn release_key(
@@ -117,5 +92,11 @@ impl KeyboardHandler for RuntimeData {
         modifiers: Modifiers,
     ) {
         info!("Update modifiers: {:?}", modifiers);
+        self.controller
+            .handle_event(InputEvent::Modifiers(controller::Modifiers {
+                ctrl: modifiers.ctrl,
+                shift: modifiers.shift,
+                alt: modifiers.alt,
+            }));
     }
 }
@@ -1,4 +1,4 @@
-use log::info;
+use log::{error, info};
 use smithay_client_toolkit::{
     delegate_seat,
     reexports::client::{protocol::wl_seat, Connection, QueueHandle},
@@ -25,11 +25,10 @@ impl SeatHandler for RuntimeData {
     ) {
         if capability == Capability::Keyboard && self.keyboard.is_none() {
             info!("Set keyboard capability");
-            let keyboard = self
-                .seat_state
-                .get_keyboard(qh, &seat, None)
-                .expect("Failed to create keyboard");
-            self.keyboard = Some(keyboard);
+            match self.seat_state.get_keyboard(qh, &seat, None) {
+                Ok(keyboard) => self.keyboard = Some(keyboard),
+                Err(why) => error!("Failed to create keyboard: {}", why),
+            }
         }
 
         if capability == Capability::Pointer
@@ -38,12 +37,16 @@ impl SeatHandler for RuntimeData {
         {
             info!("Set pointer capability");
 
-            let themed_pointer = self
+            match self
                 .seat_state
                 .get_pointer_with_theme(qh, &seat, ThemeSpec::default())
-                .expect("Failed to create themed pointer");
-            self.pointer = Some(themed_pointer.pointer().clone());
-            self.themed_pointer = Some(themed_pointer);
+            {
+                Ok(themed_pointer) => {
+                    self.pointer = Some(themed_pointer.pointer().clone());
+                    self.themed_pointer = Some(themed_pointer);
+                }
+                Err(why) => error!("Failed to create themed pointer: {}", why),
+            }
         }
     }
 
@@ -1,6 +1,5 @@
-use std::{env, fs, io::Cursor, process::Command};
+use std::{env, fs, str::FromStr};
 
-use clap::{Parser, Subcommand};
 use image::DynamicImage;
 use raw_window_handle::{
     HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
@@ -19,28 +18,29 @@ use wayland_client::{
     Connection, Proxy, QueueHandle,
 };
 
-use crate::{rendering::MonSpecificRendering, runtime_data::RuntimeData, window::WindowDescriptor};
+use crate::{
+    encode::{PngCompression, PngFilter},
+    filename,
+    rendering::MonSpecificRendering,
+    runtime_data::RuntimeData,
+    window::WindowDescriptor,
+};
 
 use crate::window::search::WindowSearchParam;
 
-#[derive(Parser, Clone, Debug)]
-#[command(author, version, about)]
-pub struct Args {
-    /// Copy the screenshot after exit
-    #[arg(short, long)]
-    pub copy: bool,
-
-    /// Output the screenshot into stdout in PNG format
-    #[arg(short, long)]
-    pub stdout: bool,
-
-    /// Path to the `grim` executable
-    #[arg(short, long)]
+/// Options for capturing the screen and the initial selection
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct Options {
+    /// Capture with `grim` instead of the built-in screencopy capture.
+    /// Optionally takes the path to the `grim` executable
+    #[arg(short, long, num_args = 0..=1, default_missing_value = "grim")]
     pub grim: Option<String>,
 
-    /// Save the image into a file
-    #[command(subcommand)]
-    pub save: Option<SaveLocation>,
+    /// Load the screenshot from an image file instead of capturing the screen.
+    /// A single file spans the whole desktop, otherwise one file per output
+    /// is expected, assigned to the outputs from left to right
+    #[arg(long = "capture-file", value_name = "PATH")]
+    pub capture_files: Vec<String>,
 
     /// Pre-selects a window by its class, title or initial versions of the two.
     /// The value passed can be a regex.
@@ -59,18 +59,20 @@ pub struct Args {
     /// Automatically captures the pre-selected window, skipping interactive mode.
     #[arg(long)]
     pub auto_capture: bool,
-}
 
-#[derive(Subcommand, Clone, Debug)]
-pub enum SaveLocation {
-    /// The path to save the image to
-    Path { path: String },
-    /// The directory to save the image to with a generated name
-    Directory { path: String },
+    /// Keeps the selection at an aspect ratio, like "16:9"
+    #[arg(long, conflicts_with = "size")]
+    pub aspect: Option<AspectRatio>,
+
+    /// Makes the selection a rectangle of a fixed size in the physical pixels of
+    /// the monitor it is placed on that can only be moved, like "1280x720"
+    #[arg(long)]
+    pub size: Option<Size>,
 }
 
 /// The configuration for colors and other things like that
 #[derive(Debug, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub handle_radius: i32,
     pub line_width: i32,
@@ -80,6 +82,55 @@ pub struct Config {
     pub text_color: Color,
     pub mode_text_size: i32,
     pub font_family: String,
+    /// Color of the arrows, lines, rectangles and ellipses
+    pub annotation_color: Color,
+    pub annotation_width: i32,
+    /// Should be translucent to leave what is highlighted visible
+    pub highlighter_color: Color,
+    pub highlighter_width: i32,
+    pub annotation_text_color: Color,
+    pub annotation_text_size: i32,
+    /// How far the blur tool spreads each pixel
+    pub blur_radius: i32,
+    /// The size of the blocks the pixelate tool averages
+    pub pixelate_size: i32,
+    /// Fill of the numbered circles, the number is black or white to stand out from it
+    pub counter_color: Color,
+    pub counter_radius: i32,
+    /// How many times larger the magnifier shows the pixels
+    pub magnifier_zoom: i32,
+    /// The label of the selection, `{width}`, `{height}`, `{x}` and `{y}` are replaced with
+    /// the logical pixels, and the same prefixed with `physical_` with the physical ones
+    pub dimensions_format: String,
+    pub dimensions_position: DimensionsPosition,
+    /// Keeps the selection at this aspect ratio, Shift does the same for a single drag
+    pub aspect: Option<AspectRatio>,
+    /// Makes the selection a rectangle of this size in physical pixels that can only be moved
+    pub size: Option<Size>,
+    /// The name of the screenshots saved into a directory, see [`crate::filename`]
+    pub filename_template: String,
+    /// Faster compression encodes quicker, but makes larger images
+    pub png_compression: PngCompression,
+    pub png_filter: PngFilter,
+    /// Store images with few colors, like screenshots of terminals, as a palette and
+    /// opaque images without alpha to make them smaller
+    pub png_optimize: bool,
+    /// Write where the screenshot came from into saved PNG and JPEG images, like the time,
+    /// the outputs and the title of the window
+    pub metadata: bool,
+}
+
+/// Where the label with the size and the position goes relative to the selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum DimensionsPosition {
+    /// Above the selection, or below it if there's no room
+    Above,
+    /// Below the selection, or above it if there's no room
+    Below,
+    /// Inside the top left corner
+    Inside,
+    Center,
+    Hidden,
 }
 
 impl Config {
@@ -115,12 +166,124 @@ impl Default for Config {
             },
             mode_text_size: 30,
             font_family: "monospace".to_string(),
+            annotation_color: Color {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            },
+            annotation_width: 4,
+            highlighter_color: Color {
+                r: 1.0,
+                g: 0.9,
+                b: 0.0,
+                a: 0.4,
+            },
+            highlighter_width: 16,
+            annotation_text_color: Color {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            },
+            annotation_text_size: 24,
+            blur_radius: 8,
+            pixelate_size: 12,
+            counter_color: Color {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            },
+            counter_radius: 14,
+            magnifier_zoom: 8,
+            dimensions_format: "{width}×{height} at {x},{y}\n{physical_width}×{physical_height} at {physical_x},{physical_y} physical".to_string(),
+            dimensions_position: DimensionsPosition::Above,
+            aspect: None,
+            size: None,
+            filename_template: filename::DEFAULT_TEMPLATE.to_string(),
+            png_compression: PngCompression::Fast,
+            png_filter: PngFilter::Adaptive,
+            png_optimize: false,
+            metadata: true,
         }
     }
 }
 
+/// The ratio of the width to the height, written as `16:9`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct AspectRatio {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl AspectRatio {
+    pub fn ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+}
+
+impl FromStr for AspectRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = parse_pair(s, ':')?;
+        Ok(Self { width, height })
+    }
+}
+
+impl TryFrom<String> for AspectRatio {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// A size in pixels, written as `1280x720`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Size {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = parse_pair(s, 'x')?;
+        Ok(Self { width, height })
+    }
+}
+
+impl TryFrom<String> for Size {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Two positive numbers separated by `separator`
+fn parse_pair(s: &str, separator: char) -> Result<(i32, i32), String> {
+    let invalid = || {
+        format!(
+            "Expected two positive numbers separated by \"{}\", got \"{}\"",
+            separator, s
+        )
+    };
+
+    let (first, second) = s.split_once(separator).ok_or_else(invalid)?;
+    match (first.trim().parse(), second.trim().parse()) {
+        (Ok(first), Ok(second)) if first > 0 && second > 0 => Ok((first, second)),
+        _ => Err(invalid()),
+    }
+}
+
 #[repr(C)]
-#[derive(Debug, Deserialize, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Deserialize, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -153,6 +316,7 @@ impl Monitor {
         conn: &Connection,
         output: wl_output::WlOutput,
         info: OutputInfo,
+        image: DynamicImage,
         runtime_data: &RuntimeData,
     ) -> Self {
         let wl_surface = runtime_data.compositor_state.create_surface(qh);
@@ -174,27 +338,6 @@ impl Monitor {
 
         layer.commit();
 
-        // Each monitor also gets their own screenshot to preserve clarity as much as possible
-        let grim_output = Command::new(
-            runtime_data
-                .args
-                .grim
-                .as_ref()
-                .unwrap_or(&"grim".to_string()),
-        )
-        .arg("-t")
-        .arg("ppm")
-        .arg("-o")
-        .arg(info.name.as_ref().unwrap())
-        .arg("-")
-        .output()
-        .expect("Failed to run grim command!")
-        .stdout;
-
-        let image =
-            image::io::Reader::with_format(Cursor::new(grim_output), image::ImageFormat::Pnm)
-                .decode()
-                .expect("Failed to parse grim image!");
         let handle = RawWgpuHandles::new(conn, &wl_surface);
 
         let surface = unsafe { runtime_data.instance.create_surface(&handle).unwrap() };
@@ -211,7 +354,7 @@ impl Monitor {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Extents {
     pub start_x: i32,
     pub start_y: i32,
@@ -316,7 +459,7 @@ impl Rect<i32> {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SelectionModifier {
     Left,
     Right,
@@ -330,7 +473,7 @@ pub enum SelectionModifier {
     Center(i32, i32, Extents),
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum Selection {
     Rectangle(Option<RectangleSelection>),
     Display(Option<DisplaySelection>),
@@ -346,11 +489,13 @@ impl Default for Selection {
 impl Selection {
     pub fn flattened(&self) -> Selection {
         match self {
-            Self::Window(Some(window)) => Self::Rectangle(Some(RectangleSelection {
-                extents: window.rect.to_extents(),
-                modifier: None,
-                active: false,
-            })),
+            Self::Window(Some(window)) => {
+                Self::Rectangle(window.rect.map(|rect| RectangleSelection {
+                    extents: rect.to_extents(),
+                    modifier: None,
+                    active: false,
+                }))
+            }
             Self::Window(None) => Self::Rectangle(None),
             _ => self.clone(),
         }
@@ -364,23 +509,22 @@ impl Selection {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct RectangleSelection {
     pub extents: Extents,
     pub modifier: Option<SelectionModifier>,
     pub active: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DisplaySelection {
-    pub wl_surface: wl_surface::WlSurface,
+    /// The rect of the selected monitor
+    pub rect: Rect<i32>,
 }
 
 impl DisplaySelection {
-    pub fn new(surface: wl_surface::WlSurface) -> Self {
-        Self {
-            wl_surface: surface,
-        }
+    pub fn new(rect: Rect<i32>) -> Self {
+        Self { rect }
     }
 }
 
@@ -404,6 +548,7 @@ pub enum MonitorIdentification {
     Surface(wl_surface::WlSurface),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ExitState {
     /// Not going to exit
     None,
@@ -411,6 +556,8 @@ pub enum ExitState {
     ExitOnly,
     /// Exit and perform actions on the selection
     ExitWithSelection(Rect<i32>),
+    /// Exit and capture the selected window by itself, as there is no geometry to crop it with
+    ExitWithWindow,
 }
 
 pub struct RawWgpuHandles {
@@ -450,3 +597,37 @@ unsafe impl HasRawDisplayHandle for RawWgpuHandles {
         self.display
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aspect_and_size_from_str() {
+        assert_eq!(
+            "16:9".parse(),
+            Ok(AspectRatio {
+                width: 16,
+                height: 9
+            })
+        );
+        assert_eq!(
+            "1280x720".parse(),
+            Ok(Size {
+                width: 1280,
+                height: 720
+            })
+        );
+
+        for invalid in ["16x9", "16:", "0:9", "-16:9", "a:b"] {
+            assert!(invalid.parse::<AspectRatio>().is_err(), "Parsing {invalid}");
+        }
+        assert!("1280:720".parse::<Size>().is_err());
+
+        let config: Config =
+            ron::from_str(r#"Config(aspect: Some("4:3"), size: Some("640x480"))"#).unwrap();
+        assert_eq!(config.aspect.map(|aspect| aspect.ratio()), Some(4.0 / 3.0));
+        assert_eq!(config.size.map(|size| size.height), Some(480));
+        assert!(ron::from_str::<Config>(r#"Config(size: Some("640"))"#).is_err());
+    }
+}
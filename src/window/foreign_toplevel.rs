@@ -0,0 +1,214 @@
+use smithay_client_toolkit::reexports::{
+    client::{event_created_child, globals::GlobalList, Connection, Dispatch, QueueHandle},
+    protocols_wlr::foreign_toplevel::v1::client::{
+        zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+        zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+    },
+};
+
+use super::{CompositorBackend, CompositorError, CompositorNotAvailable, WindowDescriptor};
+
+/// Compositor agnostic fallback built on `wlr-foreign-toplevel-management`.
+///
+/// The protocol only tells the titles, app ids and states of the windows, not
+/// where they are, so the window rects are always unknown.
+pub struct ForeignToplevelBackend {
+    windows: Vec<WindowDescriptor>,
+    focused: Option<WindowDescriptor>,
+}
+
+impl ForeignToplevelBackend {
+    /// Takes the connection of the overlay, as the protocol is a Wayland one
+    pub fn try_new(
+        conn: &Connection,
+        globals: &GlobalList,
+    ) -> Result<Box<dyn CompositorBackend>, CompositorNotAvailable> {
+        let mut event_queue = conn.new_event_queue::<ForeignToplevelState>();
+
+        let manager: ZwlrForeignToplevelManagerV1 = globals
+            .bind(&event_queue.handle(), 1..=3, ())
+            .map_err(|_| CompositorNotAvailable::Unsupported)?;
+
+        // The existing toplevels are announced right after binding
+        let mut state = ForeignToplevelState::default();
+        event_queue
+            .roundtrip(&mut state)
+            .map_err(|_| CompositorNotAvailable::NotRunning)?;
+        manager.stop();
+
+        let focused = state
+            .toplevels
+            .iter()
+            .find(|toplevel| toplevel.properties.activated)
+            .map(|toplevel| toplevel.properties.to_descriptor());
+
+        Ok(Box::new(ForeignToplevelBackend {
+            windows: state
+                .toplevels
+                .iter()
+                .map(|toplevel| toplevel.properties.to_descriptor())
+                .collect(),
+            focused,
+        }))
+    }
+}
+
+impl CompositorBackend for ForeignToplevelBackend {
+    fn get_all_windows(&self) -> Result<Vec<WindowDescriptor>, CompositorError> {
+        Ok(self.windows.clone())
+    }
+
+    fn get_focused(&self) -> Result<Option<WindowDescriptor>, CompositorError> {
+        Ok(self.focused.clone())
+    }
+
+    fn get_mouse_position(&self) -> Result<Option<(i32, i32)>, CompositorError> {
+        Ok(None)
+    }
+}
+
+/// What the protocol tells about a toplevel
+#[derive(Debug, Default)]
+struct ToplevelProperties {
+    title: String,
+    app_id: String,
+    activated: bool,
+}
+
+impl ToplevelProperties {
+    fn to_descriptor(&self) -> WindowDescriptor {
+        // The protocol does not keep track of the initial values
+        WindowDescriptor {
+            initial_title: self.title.clone(),
+            title: self.title.clone(),
+            initial_class: self.app_id.clone(),
+            class: self.app_id.clone(),
+            rect: None,
+        }
+    }
+}
+
+struct Toplevel {
+    handle: ZwlrForeignToplevelHandleV1,
+    properties: ToplevelProperties,
+}
+
+#[derive(Default)]
+struct ForeignToplevelState {
+    toplevels: Vec<Toplevel>,
+}
+
+impl Drop for ForeignToplevelState {
+    fn drop(&mut self) {
+        for toplevel in self.toplevels.drain(..) {
+            toplevel.handle.destroy();
+        }
+    }
+}
+
+/// Whether the `state` array of a toplevel, native endian `u32` values, includes activated
+fn is_activated(state: &[u8]) -> bool {
+    state
+        .chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()))
+        .any(|state| state == zwlr_foreign_toplevel_handle_v1::State::Activated as u32)
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for ForeignToplevelState {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.push(Toplevel {
+                handle: toplevel,
+                properties: ToplevelProperties::default(),
+            });
+        }
+    }
+
+    event_created_child!(ForeignToplevelState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for ForeignToplevelState {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use zwlr_foreign_toplevel_handle_v1::Event;
+
+        let Some(index) = state
+            .toplevels
+            .iter()
+            .position(|toplevel| toplevel.handle == *handle)
+        else {
+            return;
+        };
+        let properties = &mut state.toplevels[index].properties;
+
+        match event {
+            Event::Title { title } => properties.title = title,
+            Event::AppId { app_id } => properties.app_id = app_id,
+            Event::State { state } => properties.activated = is_activated(&state),
+            Event::Closed => {
+                state.toplevels.remove(index).handle.destroy();
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zwlr_foreign_toplevel_handle_v1::State;
+
+    use super::*;
+
+    fn state(states: &[State]) -> Vec<u8> {
+        states
+            .iter()
+            .flat_map(|state| (*state as u32).to_ne_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_to_descriptor() {
+        let properties = ToplevelProperties {
+            title: "~/src".to_string(),
+            app_id: "Alacritty".to_string(),
+            activated: true,
+        };
+
+        let descriptor = properties.to_descriptor();
+        assert_eq!(descriptor.title, "~/src");
+        assert_eq!(descriptor.initial_title, "~/src");
+        assert_eq!(descriptor.class, "Alacritty");
+        assert_eq!(descriptor.initial_class, "Alacritty");
+        assert_eq!(descriptor.rect, None);
+    }
+
+    #[test]
+    fn test_is_activated() {
+        assert!(is_activated(&state(&[State::Maximized, State::Activated])));
+        assert!(!is_activated(&state(&[
+            State::Maximized,
+            State::Fullscreen
+        ])));
+        assert!(!is_activated(&[]));
+
+        // A partial value at the end is ignored
+        let mut partial = state(&[State::Maximized]);
+        partial.extend(&(State::Activated as u32).to_ne_bytes()[..2]);
+        assert!(!is_activated(&partial));
+    }
+}
@@ -1,47 +1,56 @@
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    env,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
 
 use hyprland::{
-    data::{Client, Clients, CursorPosition, Monitors},
-    shared::{HyprData, HyprDataActiveOptional, WorkspaceId},
+    data::{Client, Clients, CursorPosition},
+    shared::{HyprData, HyprDataActiveOptional, HyprDataVec, HyprError, WorkspaceId},
 };
+use serde::Deserialize;
 
 use crate::types::Rect;
 
-use super::{CompositorBackend, CompositorNotAvailable, InitializeBackend, WindowDescriptor};
+use super::{
+    CompositorBackend, CompositorError, CompositorNotAvailable, InitializeBackend, WindowDescriptor,
+};
 
 pub struct HyprlandBackend;
 
+impl HyprlandBackend {
+    /// The workspaces shown on the monitors, including opened special workspaces.
+    ///
+    /// The `hyprland` crate drops the special workspaces from the monitor
+    /// data, so the monitors are requested directly.
+    fn visible_workspaces() -> Result<HashSet<WorkspaceId>, CompositorError> {
+        let reply = request("j/monitors").map_err(|why| CompositorError::Ipc(why.to_string()))?;
+        let monitors: Vec<MonitorWorkspaces> = serde_json::from_str(&reply)
+            .map_err(|why| CompositorError::InvalidReply(why.to_string()))?;
+
+        Ok(workspace_ids(&monitors))
+    }
+}
+
 impl CompositorBackend for HyprlandBackend {
-    fn get_all_windows(&self) -> Vec<WindowDescriptor> {
-        // TODO: Special Workspaces don't appear under monitors, therefore
-        // windows from specials can't be focused yet.
-        let active_workspace_ids: HashSet<WorkspaceId> = Monitors::get()
-            .unwrap()
-            .iter()
-            .map(|monitor| monitor.active_workspace.id)
-            .collect();
-
-        let mut windows: Vec<_> = Clients::get()
-            .unwrap()
-            .into_iter().filter(|client| active_workspace_ids.contains(&client.workspace.id))
-            .map(WindowDescriptor::from)
-            .collect();
-
-        windows.reverse();
-
-        windows
+    fn get_all_windows(&self) -> Result<Vec<WindowDescriptor>, CompositorError> {
+        let visible_workspaces = Self::visible_workspaces()?;
+        let clients = Clients::get().map_err(hypr_error)?;
+
+        Ok(stacked_windows(clients.to_vec(), &visible_workspaces))
     }
 
-    fn get_focused(&self) -> Option<WindowDescriptor> {
-        Client::get_active()
-            .ok()
-            .flatten()
-            .map(WindowDescriptor::from)
+    fn get_focused(&self) -> Result<Option<WindowDescriptor>, CompositorError> {
+        Ok(Client::get_active()
+            .map_err(hypr_error)?
+            .map(WindowDescriptor::from))
     }
 
-    fn get_mouse_position(&self) -> (i32, i32) {
-        let CursorPosition { x, y } = CursorPosition::get().unwrap();
-        (x as i32, y as i32)
+    fn get_mouse_position(&self) -> Result<Option<(i32, i32)>, CompositorError> {
+        let CursorPosition { x, y } = CursorPosition::get().map_err(hypr_error)?;
+        Ok(Some((x as i32, y as i32)))
     }
 }
 
@@ -62,12 +71,172 @@ impl From<Client> for WindowDescriptor {
             title: value.title,
             initial_class: value.initial_class,
             class: value.class,
-            rect: Rect {
+            rect: Some(Rect {
                 x: value.at.0 as i32,
                 y: value.at.1 as i32,
                 width: value.size.0 as i32,
                 height: value.size.1 as i32,
-            },
+            }),
         }
     }
 }
+
+#[derive(Deserialize)]
+struct WorkspaceRef {
+    id: WorkspaceId,
+}
+
+#[derive(Deserialize)]
+struct MonitorWorkspaces {
+    #[serde(rename = "activeWorkspace")]
+    active_workspace: WorkspaceRef,
+    /// Missing from older Hyprland versions
+    #[serde(rename = "specialWorkspace")]
+    special_workspace: Option<WorkspaceRef>,
+}
+
+fn workspace_ids(monitors: &[MonitorWorkspaces]) -> HashSet<WorkspaceId> {
+    monitors
+        .iter()
+        .flat_map(|monitor| {
+            // The special workspace id is 0 when none is opened
+            [
+                Some(monitor.active_workspace.id),
+                monitor
+                    .special_workspace
+                    .as_ref()
+                    .map(|workspace| workspace.id)
+                    .filter(|id| *id != 0),
+            ]
+        })
+        .flatten()
+        .collect()
+}
+
+/// The windows on the visible workspaces, topmost first
+fn stacked_windows(
+    clients: Vec<Client>,
+    visible_workspaces: &HashSet<WorkspaceId>,
+) -> Vec<WindowDescriptor> {
+    let mut clients = clients
+        .into_iter()
+        .filter(|client| client.mapped && visible_workspaces.contains(&client.workspace.id))
+        .collect::<Vec<_>>();
+
+    // Special workspaces are drawn over the regular ones, fullscreen windows over
+    // everything else on a workspace and floating windows over the tiled ones.
+    // Focusing a floating window raises it, so the recently focused are on top.
+    clients.sort_by_key(|client| {
+        (
+            client.workspace.id >= 0,
+            !client.fullscreen,
+            !(client.floating || client.pinned),
+            client.focus_history_id,
+        )
+    });
+
+    clients.into_iter().map(WindowDescriptor::from).collect()
+}
+
+fn hypr_error(why: HyprError) -> CompositorError {
+    CompositorError::Ipc(why.to_string())
+}
+
+/// Send a command to the Hyprland socket and read the whole reply
+fn request(command: &str) -> std::io::Result<String> {
+    let instance = env::var("HYPRLAND_INSTANCE_SIGNATURE")
+        .map_err(|why| std::io::Error::new(std::io::ErrorKind::NotFound, why))?;
+
+    // Newer versions keep the socket in the runtime dir instead of /tmp
+    let socket_dir = env::var("XDG_RUNTIME_DIR")
+        .map(|runtime_dir| PathBuf::from(runtime_dir).join("hypr").join(&instance))
+        .ok()
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from("/tmp/hypr").join(&instance));
+
+    let mut stream = UnixStream::connect(socket_dir.join(".socket.sock"))?;
+    stream.write_all(command.as_bytes())?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(title: &str, workspace: WorkspaceId, floating: bool, focus_history_id: i8) -> Client {
+        serde_json::from_value(serde_json::json!({
+            "address": "0x1",
+            "at": [0, 0],
+            "size": [100, 100],
+            "workspace": { "id": workspace, "name": workspace.to_string() },
+            "floating": floating,
+            "fullscreen": false,
+            "fullscreenMode": 0,
+            "monitor": 0,
+            "initialClass": "class",
+            "class": "class",
+            "initialTitle": title,
+            "title": title,
+            "pid": 1,
+            "xwayland": false,
+            "pinned": false,
+            "grouped": [],
+            "mapped": true,
+            "swallowing": null,
+            "focusHistoryID": focus_history_id,
+        }))
+        .unwrap()
+    }
+
+    fn titles(windows: &[WindowDescriptor]) -> Vec<&str> {
+        windows.iter().map(|window| window.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_floating_windows_stacked_by_focus_history() {
+        let clients = vec![
+            client("tiled", 1, false, 0),
+            client("floating old", 1, true, 3),
+            client("floating recent", 1, true, 1),
+            client("other workspace", 2, true, 2),
+        ];
+
+        let windows = stacked_windows(clients, &HashSet::from([1]));
+        assert_eq!(
+            titles(&windows),
+            ["floating recent", "floating old", "tiled"]
+        );
+    }
+
+    #[test]
+    fn test_special_workspace_on_top() {
+        let clients = vec![
+            client("floating", 1, true, 1),
+            client("scratchpad", -98, false, 2),
+            client("hidden scratchpad", -97, false, 0),
+        ];
+
+        let windows = stacked_windows(clients, &HashSet::from([1, -98]));
+        assert_eq!(titles(&windows), ["scratchpad", "floating"]);
+    }
+
+    #[test]
+    fn test_opened_special_workspaces_are_visible() {
+        let monitors: Vec<MonitorWorkspaces> = serde_json::from_str(
+            r#"[
+                { "id": 0, "activeWorkspace": { "id": 1, "name": "1" },
+                  "specialWorkspace": { "id": -98, "name": "special:magic" } },
+                { "id": 1, "activeWorkspace": { "id": 5, "name": "5" },
+                  "specialWorkspace": { "id": 0, "name": "" } },
+                { "id": 2, "activeWorkspace": { "id": 6, "name": "6" } }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(workspace_ids(&monitors), HashSet::from([1, -98, 5, 6]));
+    }
+}
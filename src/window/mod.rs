@@ -1,21 +1,29 @@
+use std::fmt;
+
 use crate::{traits::Contains, types::Rect};
 
+pub mod foreign_toplevel;
 pub mod hyprland;
+pub mod niri;
 pub mod search;
+pub mod sway;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct WindowDescriptor {
     pub initial_title: String,
     pub title: String,
     pub initial_class: String,
     pub class: String,
-    pub rect: Rect<i32>,
+    /// `None` if the compositor doesn't tell where the window is
+    pub rect: Option<Rect<i32>>,
 }
 
 pub trait CompositorBackend {
-    fn get_all_windows(&self) -> Vec<WindowDescriptor>;
-    fn get_focused(&self) -> Option<WindowDescriptor>;
-    fn get_mouse_position(&self) -> (i32, i32);
+    /// The visible windows, topmost first
+    fn get_all_windows(&self) -> Result<Vec<WindowDescriptor>, CompositorError>;
+    fn get_focused(&self) -> Result<Option<WindowDescriptor>, CompositorError>;
+    /// `None` if the compositor has no way to report the cursor position
+    fn get_mouse_position(&self) -> Result<Option<(i32, i32)>, CompositorError>;
 }
 
 pub trait InitializeBackend {
@@ -25,11 +33,14 @@ pub trait InitializeBackend {
 pub trait FindWindowExt {
     fn find_by_position(&self, position: &(i32, i32)) -> Option<&WindowDescriptor>;
     fn find_by_search_param(&self, param: search::WindowSearchParam) -> Option<&WindowDescriptor>;
+    /// The windows whose title or class contains the query, ignoring case
+    fn filter_by_query(&self, query: &str) -> Vec<&WindowDescriptor>;
 }
 
 impl FindWindowExt for Vec<WindowDescriptor> {
     fn find_by_position(&self, position: &(i32, i32)) -> Option<&WindowDescriptor> {
-        self.iter().find(|window| window.rect.contains(position))
+        self.iter()
+            .find(|window| window.rect.is_some_and(|rect| rect.contains(position)))
     }
 
     fn find_by_search_param(&self, param: search::WindowSearchParam) -> Option<&WindowDescriptor> {
@@ -46,9 +57,112 @@ impl FindWindowExt for Vec<WindowDescriptor> {
             param.value.is_match(attr_value)
         })
     }
+
+    fn filter_by_query(&self, query: &str) -> Vec<&WindowDescriptor> {
+        let query = query.to_lowercase();
+
+        self.iter()
+            .filter(|window| {
+                window.title.to_lowercase().contains(&query)
+                    || window.class.to_lowercase().contains(&query)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum CompositorError {
+    /// Communicating with the compositor failed
+    Ipc(String),
+    /// The compositor replied with something unexpected
+    InvalidReply(String),
+}
+
+impl fmt::Display for CompositorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ipc(why) => write!(f, "Compositor IPC failed: {}", why),
+            Self::InvalidReply(why) => write!(f, "Invalid reply from the compositor: {}", why),
+        }
+    }
 }
 
+impl std::error::Error for CompositorError {}
+
 pub enum CompositorNotAvailable {
     NotInstalled,
     NotRunning,
+    /// The compositor lacks the protocol the backend needs
+    Unsupported,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::net::{UnixListener, UnixStream},
+        path::PathBuf,
+        thread,
+    };
+
+    use super::*;
+
+    /// A fake compositor IPC socket in its own temporary directory, which is removed on drop
+    pub(super) struct FakeIpc {
+        directory: PathBuf,
+        pub path: PathBuf,
+    }
+
+    impl FakeIpc {
+        /// Answer every connection to the socket with `handle` on a background thread
+        pub fn serve(name: &str, handle: impl Fn(UnixStream) + Send + 'static) -> Self {
+            let directory =
+                std::env::temp_dir().join(format!("watershot-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&directory);
+            std::fs::create_dir_all(&directory).unwrap();
+
+            let path = directory.join("ipc.sock");
+            let listener = UnixListener::bind(&path).unwrap();
+            thread::spawn(move || listener.incoming().flatten().for_each(handle));
+
+            Self { directory, path }
+        }
+    }
+
+    impl Drop for FakeIpc {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.directory);
+        }
+    }
+
+    fn window(title: &str, class: &str) -> WindowDescriptor {
+        WindowDescriptor {
+            initial_title: title.to_string(),
+            title: title.to_string(),
+            initial_class: class.to_string(),
+            class: class.to_string(),
+            rect: None,
+        }
+    }
+
+    #[test]
+    fn test_filter_by_query() {
+        let windows = vec![
+            window("Inbox - Mail", "thunderbird"),
+            window("~/src", "Alacritty"),
+            window("Alacritty docs", "firefox"),
+        ];
+
+        let titles = |query| {
+            windows
+                .filter_by_query(query)
+                .into_iter()
+                .map(|window| window.title.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(titles(""), ["Inbox - Mail", "~/src", "Alacritty docs"]);
+        assert_eq!(titles("alacritty"), ["~/src", "Alacritty docs"]);
+        assert_eq!(titles("MAIL"), ["Inbox - Mail"]);
+        assert!(titles("nothing").is_empty());
+    }
 }
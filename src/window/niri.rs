@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::types::Rect;
+
+use super::{
+    CompositorBackend, CompositorError, CompositorNotAvailable, InitializeBackend, WindowDescriptor,
+};
+
+/// Talks to niri over the JSON IPC socket in `$NIRI_SOCKET`
+pub struct NiriBackend {
+    socket_path: PathBuf,
+}
+
+impl NiriBackend {
+    pub fn new(socket_path: PathBuf) -> Self {
+        Self { socket_path }
+    }
+
+    /// Send a request without arguments like `Windows` and return the reply of the same name
+    fn request<T: DeserializeOwned>(&self, request: &str) -> Result<T, CompositorError> {
+        let line = self
+            .send(request)
+            .map_err(|why| CompositorError::Ipc(why.to_string()))?;
+
+        let reply: Result<HashMap<String, serde_json::Value>, String> =
+            serde_json::from_str(&line).map_err(invalid_reply)?;
+        let mut reply = reply.map_err(CompositorError::Ipc)?;
+
+        let value = reply
+            .remove(request)
+            .ok_or(CompositorError::InvalidReply(format!(
+                "Unexpected reply to {} request",
+                request
+            )))?;
+
+        serde_json::from_value(value).map_err(invalid_reply)
+    }
+
+    fn send(&self, request: &str) -> io::Result<String> {
+        let mut stream = UnixStream::connect(&self.socket_path)?;
+        stream.write_all(format!("\"{}\"\n", request).as_bytes())?;
+
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+
+        Ok(line)
+    }
+
+    /// The windows on the active workspaces that are in view, topmost first
+    fn visible_windows(&self) -> Result<Vec<(Window, WindowDescriptor)>, CompositorError> {
+        let outputs: HashMap<String, Output> = self.request("Outputs")?;
+        let workspaces: Vec<Workspace> = self.request("Workspaces")?;
+        let mut windows: Vec<Window> = self.request("Windows")?;
+
+        // Floating windows are above the tiled ones, and the focused one above the other floating ones
+        windows.sort_by_key(|window| (!window.is_floating, !window.is_focused));
+
+        Ok(windows
+            .into_iter()
+            .filter_map(|window| {
+                let workspace = workspaces
+                    .iter()
+                    .find(|workspace| Some(workspace.id) == window.workspace_id)
+                    .filter(|workspace| workspace.is_active)?;
+                let output = outputs.get(workspace.output.as_ref()?)?.logical.as_ref()?;
+                let descriptor = window.to_descriptor(output)?;
+
+                Some((window, descriptor))
+            })
+            .collect())
+    }
+}
+
+impl CompositorBackend for NiriBackend {
+    fn get_all_windows(&self) -> Result<Vec<WindowDescriptor>, CompositorError> {
+        Ok(self
+            .visible_windows()?
+            .into_iter()
+            .map(|(_, descriptor)| descriptor)
+            .collect())
+    }
+
+    fn get_focused(&self) -> Result<Option<WindowDescriptor>, CompositorError> {
+        Ok(self
+            .visible_windows()?
+            .into_iter()
+            .find(|(window, _)| window.is_focused)
+            .map(|(_, descriptor)| descriptor))
+    }
+
+    fn get_mouse_position(&self) -> Result<Option<(i32, i32)>, CompositorError> {
+        // The IPC has no way to query the cursor, it is known once the pointer enters the overlay
+        Ok(None)
+    }
+}
+
+fn invalid_reply(why: serde_json::Error) -> CompositorError {
+    CompositorError::InvalidReply(why.to_string())
+}
+
+impl InitializeBackend for NiriBackend {
+    fn try_new() -> Result<Box<dyn CompositorBackend>, CompositorNotAvailable> {
+        match std::env::var_os("NIRI_SOCKET") {
+            Some(socket_path) => Ok(Box::new(NiriBackend::new(socket_path.into()))),
+            None => Err(CompositorNotAvailable::NotRunning),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LogicalOutput {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Deserialize)]
+struct Output {
+    /// Missing for disabled outputs
+    logical: Option<LogicalOutput>,
+}
+
+#[derive(Deserialize)]
+struct Workspace {
+    id: u64,
+    output: Option<String>,
+    is_active: bool,
+}
+
+#[derive(Deserialize)]
+struct WindowLayout {
+    window_size: (i32, i32),
+    /// Relative to the output, missing if the window is scrolled out of view
+    tile_pos_in_workspace_view: Option<(f64, f64)>,
+    window_offset_in_tile: (f64, f64),
+}
+
+#[derive(Deserialize)]
+struct Window {
+    title: Option<String>,
+    app_id: Option<String>,
+    workspace_id: Option<u64>,
+    is_focused: bool,
+    is_floating: bool,
+    layout: WindowLayout,
+}
+
+impl Window {
+    fn to_descriptor(&self, output: &LogicalOutput) -> Option<WindowDescriptor> {
+        let (tile_x, tile_y) = self.layout.tile_pos_in_workspace_view?;
+        let (offset_x, offset_y) = self.layout.window_offset_in_tile;
+        let (width, height) = self.layout.window_size;
+
+        let title = self.title.clone().unwrap_or_default();
+        let class = self.app_id.clone().unwrap_or_default();
+
+        // niri does not keep track of the initial values
+        Some(WindowDescriptor {
+            initial_title: title.clone(),
+            title,
+            initial_class: class.clone(),
+            class,
+            rect: Some(Rect {
+                x: output.x + (tile_x + offset_x).round() as i32,
+                y: output.y + (tile_y + offset_y).round() as i32,
+                width,
+                height,
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window::tests::FakeIpc;
+
+    const OUTPUTS: &str = r#"{"Ok":{"Outputs":{"DP-1":{"name":"DP-1","logical":{"x":0,"y":0,"width":1920,"height":1080,"scale":1.0,"transform":"Normal"}},"HDMI-A-1":{"name":"HDMI-A-1","logical":{"x":1920,"y":0,"width":1280,"height":720,"scale":1.5,"transform":"Normal"}}}}}"#;
+    const WORKSPACES: &str = r#"{"Ok":{"Workspaces":[{"id":1,"idx":1,"name":null,"output":"DP-1","is_active":true,"is_focused":true,"active_window_id":3},{"id":2,"idx":2,"name":null,"output":"DP-1","is_active":false,"is_focused":false,"active_window_id":4},{"id":3,"idx":1,"name":"web","output":"HDMI-A-1","is_active":true,"is_focused":false,"active_window_id":5}]}}"#;
+    const WINDOWS: &str = r#"{"Ok":{"Windows":[{"id":1,"title":"Terminal","app_id":"Alacritty","pid":100,"workspace_id":1,"is_focused":false,"is_floating":false,"layout":{"pos_in_scrolling_layout":[1,1],"tile_size":[960.0,1064.0],"window_size":[956,1060],"tile_pos_in_workspace_view":[8.0,8.0],"window_offset_in_tile":[2.0,2.0]}},{"id":2,"title":"Scrolled away","app_id":"foot","pid":101,"workspace_id":1,"is_focused":false,"is_floating":false,"layout":{"pos_in_scrolling_layout":[3,1],"tile_size":[960.0,1064.0],"window_size":[960,1064],"tile_pos_in_workspace_view":null,"window_offset_in_tile":[0.0,0.0]}},{"id":3,"title":"Picture-in-Picture","app_id":"firefox","pid":102,"workspace_id":1,"is_focused":true,"is_floating":true,"layout":{"pos_in_scrolling_layout":null,"tile_size":[400.0,225.0],"window_size":[400,225],"tile_pos_in_workspace_view":[100.5,200.0],"window_offset_in_tile":[0.0,0.0]}},{"id":4,"title":"Inactive workspace","app_id":"foot","pid":103,"workspace_id":2,"is_focused":false,"is_floating":false,"layout":{"pos_in_scrolling_layout":[1,1],"tile_size":[960.0,1064.0],"window_size":[960,1064],"tile_pos_in_workspace_view":[0.0,0.0],"window_offset_in_tile":[0.0,0.0]}},{"id":5,"title":"Browser","app_id":null,"pid":104,"workspace_id":3,"is_focused":false,"is_floating":false,"layout":{"pos_in_scrolling_layout":[1,1],"tile_size":[1280.0,720.0],"window_size":[1280,720],"tile_pos_in_workspace_view":[0.0,0.0],"window_offset_in_tile":[0.0,0.0]}}]}}"#;
+
+    /// Replay the recorded responses to the requests on a stub socket
+    fn serve_stub_ipc(name: &str) -> FakeIpc {
+        FakeIpc::serve(name, |mut stream| {
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+
+            let reply = match request.trim() {
+                "\"Outputs\"" => OUTPUTS,
+                "\"Workspaces\"" => WORKSPACES,
+                "\"Windows\"" => WINDOWS,
+                _ => r#"{"Err":"unknown request"}"#,
+            };
+            stream.write_all(format!("{}\n", reply).as_bytes()).unwrap();
+        })
+    }
+
+    #[test]
+    fn test_windows_on_active_workspaces() {
+        let ipc = serve_stub_ipc("niri-windows");
+        let backend = NiriBackend::new(ipc.path.clone());
+
+        let windows = backend.get_all_windows().unwrap();
+        let titles = windows
+            .iter()
+            .map(|window| window.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Picture-in-Picture", "Terminal", "Browser"]);
+
+        assert_eq!(windows[0].class, "firefox");
+        assert_eq!(windows[1].rect, Some(Rect::new(10, 10, 956, 1060)));
+        // Offset by the position of the output
+        assert_eq!(windows[2].rect, Some(Rect::new(1920, 0, 1280, 720)));
+        assert_eq!(windows[2].class, "");
+    }
+
+    #[test]
+    fn test_focused_window() {
+        let ipc = serve_stub_ipc("niri-focused");
+        let backend = NiriBackend::new(ipc.path.clone());
+
+        let focused = backend.get_focused().unwrap().unwrap();
+        assert_eq!(focused.title, "Picture-in-Picture");
+        assert_eq!(focused.rect.unwrap().x, 101);
+    }
+
+    #[test]
+    fn test_error_reply() {
+        let ipc = serve_stub_ipc("niri-error");
+        let backend = NiriBackend::new(ipc.path.clone());
+
+        assert!(matches!(
+            backend.request::<String>("Version"),
+            Err(CompositorError::Ipc(why)) if why == "unknown request"
+        ));
+    }
+}
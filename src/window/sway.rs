@@ -0,0 +1,304 @@
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use serde::Deserialize;
+
+use crate::types::Rect;
+
+use super::{
+    CompositorBackend, CompositorError, CompositorNotAvailable, InitializeBackend, WindowDescriptor,
+};
+
+const IPC_MAGIC: &[u8] = b"i3-ipc";
+const IPC_GET_TREE: u32 = 4;
+
+/// Talks to sway over its i3 compatible IPC socket
+pub struct SwayBackend {
+    socket_path: PathBuf,
+}
+
+impl SwayBackend {
+    pub fn new(socket_path: PathBuf) -> Self {
+        Self { socket_path }
+    }
+
+    /// Send a single message and wait for the reply payload
+    fn request(&self, message_type: u32, payload: &[u8]) -> Result<Vec<u8>, CompositorError> {
+        self.try_request(message_type, payload)
+            .map_err(|why| CompositorError::Ipc(why.to_string()))
+    }
+
+    fn try_request(&self, message_type: u32, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let mut stream = UnixStream::connect(&self.socket_path)?;
+
+        let mut message = IPC_MAGIC.to_vec();
+        message.extend((payload.len() as u32).to_ne_bytes());
+        message.extend(message_type.to_ne_bytes());
+        message.extend(payload);
+        stream.write_all(&message)?;
+
+        let mut header = [0; 14];
+        stream.read_exact(&mut header)?;
+        if &header[..6] != IPC_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid IPC reply header",
+            ));
+        }
+
+        let len = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+        let mut reply = vec![0; len as usize];
+        stream.read_exact(&mut reply)?;
+
+        Ok(reply)
+    }
+
+    fn get_tree(&self) -> Result<Node, CompositorError> {
+        let reply = self.request(IPC_GET_TREE, &[])?;
+
+        serde_json::from_slice(&reply).map_err(|why| CompositorError::InvalidReply(why.to_string()))
+    }
+}
+
+impl CompositorBackend for SwayBackend {
+    fn get_all_windows(&self) -> Result<Vec<WindowDescriptor>, CompositorError> {
+        let mut windows = Vec::new();
+        self.get_tree()?.collect_windows(&mut windows);
+
+        Ok(windows)
+    }
+
+    fn get_focused(&self) -> Result<Option<WindowDescriptor>, CompositorError> {
+        Ok(self
+            .get_tree()?
+            .find_focused()
+            .filter(|node| node.is_window())
+            .map(WindowDescriptor::from))
+    }
+
+    fn get_mouse_position(&self) -> Result<Option<(i32, i32)>, CompositorError> {
+        // The IPC has no way to query the cursor, it is known once the pointer enters the overlay
+        Ok(None)
+    }
+}
+
+impl InitializeBackend for SwayBackend {
+    fn try_new() -> Result<Box<dyn CompositorBackend>, CompositorNotAvailable> {
+        match std::env::var_os("SWAYSOCK") {
+            Some(socket_path) => Ok(Box::new(SwayBackend::new(socket_path.into()))),
+            None => Err(CompositorNotAvailable::NotRunning),
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(default)]
+struct NodeRect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct WindowProperties {
+    class: Option<String>,
+    title: Option<String>,
+}
+
+/// The parts of a `get_tree` node that are of interest
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Node {
+    #[serde(rename = "type")]
+    node_type: String,
+    name: Option<String>,
+    focused: bool,
+    /// Only present on views
+    visible: Option<bool>,
+    rect: NodeRect,
+    /// Geometry of the window contents relative to `rect`
+    window_rect: NodeRect,
+    /// Set for native Wayland windows
+    app_id: Option<String>,
+    /// Set for Xwayland windows
+    window_properties: Option<WindowProperties>,
+    nodes: Vec<Node>,
+    floating_nodes: Vec<Node>,
+}
+
+impl Node {
+    fn is_window(&self) -> bool {
+        matches!(self.node_type.as_str(), "con" | "floating_con") && self.visible.is_some()
+    }
+
+    /// Collect the visible windows, topmost first
+    fn collect_windows(&self, windows: &mut Vec<WindowDescriptor>) {
+        if self.is_window() {
+            if self.visible == Some(true) {
+                windows.push(WindowDescriptor::from(self));
+            }
+            return;
+        }
+
+        // Floating windows are stacked above the tiled ones, last one on top
+        for node in self.floating_nodes.iter().rev() {
+            node.collect_windows(windows);
+        }
+        for node in &self.nodes {
+            node.collect_windows(windows);
+        }
+    }
+
+    fn find_focused(&self) -> Option<&Node> {
+        if self.focused {
+            return Some(self);
+        }
+
+        self.nodes
+            .iter()
+            .chain(&self.floating_nodes)
+            .find_map(Node::find_focused)
+    }
+}
+
+impl From<&Node> for WindowDescriptor {
+    fn from(node: &Node) -> Self {
+        let properties = node.window_properties.as_ref();
+        let class = node
+            .app_id
+            .clone()
+            .or_else(|| properties.and_then(|properties| properties.class.clone()))
+            .unwrap_or_default();
+        let title = node
+            .name
+            .clone()
+            .or_else(|| properties.and_then(|properties| properties.title.clone()))
+            .unwrap_or_default();
+
+        // Sway does not keep track of the initial values
+        Self {
+            initial_title: title.clone(),
+            title,
+            initial_class: class.clone(),
+            class,
+            rect: Some(Rect {
+                x: node.rect.x + node.window_rect.x,
+                y: node.rect.y + node.window_rect.y,
+                width: node.window_rect.width,
+                height: node.window_rect.height,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window::tests::FakeIpc;
+
+    const TREE: &str = r#"{
+        "type": "root", "name": "root", "focused": false,
+        "rect": { "x": 0, "y": 0, "width": 3840, "height": 1080 },
+        "nodes": [{
+            "type": "output", "name": "DP-1", "focused": false,
+            "rect": { "x": 0, "y": 0, "width": 1920, "height": 1080 },
+            "nodes": [{
+                "type": "workspace", "name": "1", "focused": false,
+                "rect": { "x": 0, "y": 0, "width": 1920, "height": 1080 },
+                "nodes": [{
+                    "type": "con", "name": "Terminal", "focused": true, "visible": true,
+                    "app_id": "foot",
+                    "rect": { "x": 0, "y": 0, "width": 960, "height": 1080 },
+                    "window_rect": { "x": 2, "y": 2, "width": 956, "height": 1076 },
+                    "nodes": [], "floating_nodes": []
+                }, {
+                    "type": "con", "name": null, "focused": false,
+                    "layout": "tabbed",
+                    "rect": { "x": 960, "y": 0, "width": 960, "height": 1080 },
+                    "nodes": [{
+                        "type": "con", "name": "Hidden tab", "focused": false, "visible": false,
+                        "app_id": "firefox",
+                        "rect": { "x": 960, "y": 24, "width": 960, "height": 1056 },
+                        "window_rect": { "x": 0, "y": 0, "width": 960, "height": 1056 },
+                        "nodes": [], "floating_nodes": []
+                    }, {
+                        "type": "con", "name": "Visible tab", "focused": false, "visible": true,
+                        "app_id": null,
+                        "window_properties": { "class": "Gimp", "title": "Visible tab" },
+                        "rect": { "x": 960, "y": 24, "width": 960, "height": 1056 },
+                        "window_rect": { "x": 0, "y": 0, "width": 960, "height": 1056 },
+                        "nodes": [], "floating_nodes": []
+                    }],
+                    "floating_nodes": []
+                }],
+                "floating_nodes": [{
+                    "type": "floating_con", "name": "Below", "focused": false, "visible": true,
+                    "app_id": "pavucontrol",
+                    "rect": { "x": 100, "y": 100, "width": 400, "height": 300 },
+                    "window_rect": { "x": 0, "y": 0, "width": 400, "height": 300 },
+                    "nodes": [], "floating_nodes": []
+                }, {
+                    "type": "floating_con", "name": "Above", "focused": false, "visible": true,
+                    "app_id": "mpv",
+                    "rect": { "x": 200, "y": 200, "width": 400, "height": 300 },
+                    "window_rect": { "x": 0, "y": 0, "width": 400, "height": 300 },
+                    "nodes": [], "floating_nodes": []
+                }]
+            }],
+            "floating_nodes": []
+        }],
+        "floating_nodes": []
+    }"#;
+
+    /// Serve a canned reply to every request on a fake IPC socket
+    fn serve_fake_ipc(name: &str, reply: &'static str) -> FakeIpc {
+        FakeIpc::serve(name, move |mut stream| {
+            let mut header = [0; 14];
+            stream.read_exact(&mut header).unwrap();
+            assert_eq!(&header[..6], IPC_MAGIC);
+            assert_eq!(
+                u32::from_ne_bytes(header[10..14].try_into().unwrap()),
+                IPC_GET_TREE
+            );
+
+            let mut message = IPC_MAGIC.to_vec();
+            message.extend((reply.len() as u32).to_ne_bytes());
+            message.extend(IPC_GET_TREE.to_ne_bytes());
+            message.extend(reply.as_bytes());
+            stream.write_all(&message).unwrap();
+        })
+    }
+
+    #[test]
+    fn test_visible_windows_topmost_first() {
+        let ipc = serve_fake_ipc("sway-windows", TREE);
+        let backend = SwayBackend::new(ipc.path.clone());
+
+        let windows = backend.get_all_windows().unwrap();
+        let titles = windows
+            .iter()
+            .map(|window| window.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Above", "Below", "Terminal", "Visible tab"]);
+
+        assert_eq!(windows[2].class, "foot");
+        assert_eq!(windows[2].rect, Some(Rect::new(2, 2, 956, 1076)));
+        // Xwayland windows only have a class
+        assert_eq!(windows[3].class, "Gimp");
+    }
+
+    #[test]
+    fn test_focused_window() {
+        let ipc = serve_fake_ipc("sway-focused", TREE);
+        let backend = SwayBackend::new(ipc.path.clone());
+
+        let focused = backend.get_focused().unwrap().unwrap();
+        assert_eq!(focused.title, "Terminal");
+        assert_eq!(focused.class, "foot");
+    }
+}